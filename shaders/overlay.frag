@@ -0,0 +1,43 @@
+#version 150 core
+
+// Draws text over a dimmed background. Each texel of tText is a character: its code in red and its
+// style in green. tFont has the glyphs of ASCII 32-126, 16 per row, in cells of 8x16 texels.
+
+uniform vec3      iResolution;
+uniform sampler2D tFont;
+uniform sampler2D tText;
+uniform float     uScale;
+
+in vec2 fragCoord;
+out vec4 fragColor;
+
+const vec2 CELL = vec2(8.0, 16.0);
+const float MARGIN = 8.0;
+
+const vec3 STYLES[4] = vec3[4](
+    vec3(0.9, 0.9, 0.9),  // Text.
+    vec3(1.0, 0.35, 0.35), // Errors.
+    vec3(1.0, 0.8, 0.3),  // Warnings.
+    vec3(0.4, 0.6, 1.0)   // Locations and the gutter.
+);
+
+void main() {
+    // Position in unscaled pixels from the top left corner of the text.
+    vec2 p = vec2(fragCoord.x, iResolution.y - fragCoord.y) / uScale - MARGIN;
+    ivec2 cell = ivec2(floor(p / CELL));
+
+    float coverage = 0.0;
+    vec3 color = STYLES[0];
+    if (all(greaterThanEqual(p, vec2(0.0))) && all(lessThan(cell, textureSize(tText, 0)))) {
+        vec4 texel = texelFetch(tText, cell, 0);
+        int code = int(texel.r * 255.0 + 0.5) - 32;
+        int style = int(texel.g * 255.0 + 0.5);
+        if (code >= 0 && code < 95) {
+            ivec2 glyph = ivec2(code % 16, code / 16) * ivec2(CELL);
+            coverage = texelFetch(tFont, glyph + ivec2(mod(p, CELL)), 0).r;
+        }
+        color = STYLES[clamp(style, 0, 3)];
+    }
+
+    fragColor = vec4(color * coverage, mix(0.8, 1.0, coverage));
+}
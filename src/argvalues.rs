@@ -1,15 +1,40 @@
-use error;
-use gfx::texture::{FilterMethod, WrapMode};
+use batch::Batch;
+use config::Config;
+use download::{self, Downloader, OnExisting, Query};
+use error::{self, InvalidDateError, InvalidMouseError, ZeroCountError};
+use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+use loader;
+use pass::{BufferId, ChannelBinding};
+use project::{self, Project};
+use renderer::{self, ShaderConfig, TextureId, TextureSource, VolumeSource};
 
 use clap::App;
 
+use std::env;
+use std::path::PathBuf;
+
 pub struct ArgValues {
     pub width: f32,
     pub height: f32,
 
-    // Path to the shader. None if using default fragment shader.
+    // Path to the shader or project manifest. None if using default fragment shader.
     pub shaderpath: Option<String>,
 
+    // Path to code shared by all passes (Shadertoy's "Common" tab). None if there is none.
+    pub commonpath: Option<String>,
+
+    // Paths to the Buffer A-D shaders. None if the buffer pass is unused.
+    pub buffer_a_path: Option<String>,
+    pub buffer_b_path: Option<String>,
+    pub buffer_c_path: Option<String>,
+    pub buffer_d_path: Option<String>,
+
+    // Path to the Cube A shader. None if the pass is unused.
+    pub cube_a_path: Option<String>,
+
+    // Channel bindings that override the default of iChannelN reading the n-th texture.
+    pub channel_bindings: Vec<ChannelBinding>,
+
     // Path to the n-th texture. None if using default textures.
     pub texture0path: Option<String>,
     pub texture1path: Option<String>,
@@ -28,10 +53,6 @@ pub struct ArgValues {
     pub filter2: FilterMethod,
     pub filter3: FilterMethod,
 
-    // Max value for anisotropic filtering. Defaults to 1 if unspecified. Only needed for
-    // "anisotropic" filter method.
-    pub anisotropic_max: u8,
-
     // Some(name) if running an example.
     pub examplename: Option<String>,
 
@@ -41,11 +62,74 @@ pub struct ArgValues {
     // Custom window title. Defaults to "{shader name} - shadertoy-rs".
     pub title: Option<String>,
 
+    // Some(batch) if downloading several shaders (getid is None then).
+    pub batch: Option<Batch>,
+
     // True if also running downloaded shader.
     pub andrun: bool,
 
+    // Base URL to download shaders and their media from.
+    pub base_url: String,
+
+    // Key for the Shadertoy API. None to use the site's own endpoint.
+    pub api_key: Option<String>,
+
+    // Maximum number of requests per second when downloading (0 for no limit).
+    pub download_rate: f32,
+
+    // Directory downloaded shaders are saved in. Defaults to the current directory.
+    pub download_dir: PathBuf,
+
+    // What to do if a downloaded shader was already saved. Defaults to skipping it.
+    pub on_existing: OnExisting,
+
+    // Shadertoy id if running a downloaded shader from the cache.
+    pub runid: Option<String>,
+
+    // Some(terms) if listing downloaded shaders matching all the terms (all shaders if empty).
+    pub search_terms: Option<Vec<String>>,
+
     // True if we should force disable srgb
     pub force_srgb_off: bool,
+
+    // True to follow the older iMouse convention, with no click signal in iMouse.w.
+    pub legacy_mouse: bool,
+
+    // Path to the sound shader if rendering sound.
+    pub soundpath: Option<String>,
+
+    // WAV file to write rendered sound to. Defaults to "sound.wav".
+    pub sound_outpath: String,
+
+    // Length of rendered sound in seconds. Defaults to 180, as on shadertoy.com.
+    pub sound_duration: f32,
+
+    // Sample rate for rendered sound, also reported through iSampleRate. Defaults to 44100.
+    pub sample_rate: u32,
+
+    // True if rendered sound should also be played.
+    pub play_sound: bool,
+
+    // Image file to render a single frame to. None if not rendering an image.
+    pub render_outpath: Option<String>,
+
+    // Values of iTime and iFrame for the rendered frame.
+    pub render_time: f32,
+    pub render_frame: i32,
+
+    // Value of iMouse for rendered or recorded frames.
+    pub render_mouse: [f32; 4],
+
+    // Fixed value of iDate for rendered or recorded frames. None to use the current date.
+    pub render_date: Option<[f32; 4]>,
+
+    // Where to write recorded frames. None if not recording.
+    pub record_outpath: Option<String>,
+
+    // Number of recorded frames, their rate and the iTime of the first one.
+    pub record_frames: u32,
+    pub record_fps: u32,
+    pub record_start: f32,
 }
 
 impl ArgValues {
@@ -58,14 +142,55 @@ impl ArgValues {
         let str_to_string = |s: &str| s.to_string();
 
         // Window dimensions.
-        let width = matches.value_of("width").unwrap().parse()?;
-        let height = matches.value_of("height").unwrap().parse()?;
+        let mut width = matches.value_of("width").unwrap().parse()?;
+        let mut height = matches.value_of("height").unwrap().parse()?;
 
         // Check to see if they want an example run.
         let examplename = matches.value_of("example").map(&str_to_string);
 
-        // Fragment shader path.
-        let shaderpath = matches.value_of("shader").map(&str_to_string);
+        // Fragment shader path. A shader given to the render or record subcommand takes
+        // precedence.
+        let rendermatches = matches.subcommand_matches("render");
+        let recordmatches = matches.subcommand_matches("record");
+        let shaderpath = rendermatches
+            .or(recordmatches)
+            .and_then(|m| m.value_of("shader"))
+            .or_else(|| matches.value_of("shader"))
+            .map(&str_to_string);
+
+        // A project manifest provides defaults for the window size and title.
+        let manifest = match shaderpath {
+            Some(ref path) if examplename.is_none() && project::is_manifest(path) => {
+                Some(Project::load(path)?.manifest)
+            }
+            _ => None,
+        };
+        if let Some(ref manifest) = manifest {
+            if let (Some(w), 0) = (manifest.width, matches.occurrences_of("width")) {
+                width = w as f32;
+            }
+            if let (Some(h), 0) = (manifest.height, matches.occurrences_of("height")) {
+                height = h as f32;
+            }
+        }
+
+        // Common code path.
+        let commonpath = matches.value_of("common").map(&str_to_string);
+
+        // Buffer shader paths.
+        let buffer_a_path = matches.value_of("buffer_a").map(&str_to_string);
+        let buffer_b_path = matches.value_of("buffer_b").map(&str_to_string);
+        let buffer_c_path = matches.value_of("buffer_c").map(&str_to_string);
+        let buffer_d_path = matches.value_of("buffer_d").map(&str_to_string);
+
+        // Cube A shader path.
+        let cube_a_path = matches.value_of("cube_a").map(&str_to_string);
+
+        // Channel bindings.
+        let channel_bindings = matches
+            .values_of("channel")
+            .map(|bindings| bindings.map(ChannelBinding::parse).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))?;
 
         // Texture paths.
         let texture0path = matches.value_of("texture0").map(&str_to_string);
@@ -109,24 +234,154 @@ impl ArgValues {
         let filter3 = get_filter_mode(&matches.value_of("filter3"));
 
         // Window title.
-        let title = matches.value_of("title").map(&str_to_string);
-
-        // Check to see if they want to download a shader (and then run it).
-        let (getid, andrun) = if let Some(getmatches) = matches.subcommand_matches("get") {
-            (
-                getmatches.value_of("id").map(&str_to_string),
-                getmatches.is_present("run"),
-            )
+        let title = matches
+            .value_of("title")
+            .map(&str_to_string)
+            .or_else(|| manifest.and_then(|manifest| manifest.title));
+
+        // Check to see if they want to download shaders (and then run one).
+        let getmatches = matches.subcommand_matches("get");
+        let (mut getid, mut batch) = (None, None);
+        if let Some(getmatches) = getmatches {
+            let values = |name| {
+                getmatches
+                    .values_of(name)
+                    .map(|values| values.map(&str_to_string).collect())
+                    .unwrap_or_else(Vec::new)
+            };
+            let query = match getmatches.value_of("query") {
+                Some(terms) => Some(Query {
+                    terms: terms.to_string(),
+                    sort: getmatches.value_of("sort").map(&str_to_string),
+                    filters: values("filter"),
+                    limit: getmatches.value_of("limit").unwrap_or("25").parse()?,
+                }),
+                None => None,
+            };
+            let get = Batch {
+                ids: values("ids"),
+                file: getmatches.value_of("file").map(&str_to_string),
+                query,
+                tags: values("tag"),
+                jobs: getmatches.value_of("jobs").unwrap().parse()?,
+            };
+            if get.is_single() {
+                getid = get.ids.into_iter().next();
+            } else {
+                batch = Some(get);
+            }
+        }
+        let andrun = getmatches.is_some_and(|m| m.is_present("run"));
+        let download_rate = getmatches
+            .and_then(|m| m.value_of("rate"))
+            .unwrap_or("4")
+            .parse()?;
+        let download_dir = getmatches
+            .and_then(|m| m.value_of("output"))
+            .map_or_else(PathBuf::new, PathBuf::from);
+        let on_existing = match getmatches.and_then(|m| m.value_of("overwrite")) {
+            Some("overwrite") => OnExisting::Overwrite,
+            Some("rename") => OnExisting::Rename,
+            _ => OnExisting::Skip,
+        };
+        // Only downloads use the config file, so a broken one doesn't stop anything else.
+        let config = if getmatches.is_some() {
+            Config::load()?
+        } else {
+            Config::default()
+        };
+        let base_url = getmatches
+            .and_then(|m| m.value_of("base_url"))
+            .map(&str_to_string)
+            .or_else(|| env::var("SHADERTOY_BASE_URL").ok())
+            .or(config.base_url)
+            .unwrap_or_else(|| download::DEFAULT_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+        let api_key = env::var("SHADERTOY_API_KEY").ok().or(config.api_key);
+
+        // Check to see if they want to run a cached shader, or list or search cached shaders.
+        let runid = matches
+            .subcommand_matches("run")
+            .and_then(|m| m.value_of("id"))
+            .map(&str_to_string);
+        let search_terms = if matches.subcommand_matches("list").is_some() {
+            Some(Vec::new())
         } else {
-            (None, false)
+            matches
+                .subcommand_matches("search")
+                .and_then(|m| m.values_of("terms"))
+                .map(|terms| terms.map(&str_to_string).collect())
         };
 
         let force_srgb_off = matches.is_present("force_srgb_off");
+        let legacy_mouse = matches.is_present("legacy_mouse");
+
+        // Check to see if they want to render sound.
+        let soundmatches = matches.subcommand_matches("sound");
+        let soundpath = soundmatches.and_then(|m| m.value_of("shader").map(&str_to_string));
+        let sound_outpath = soundmatches
+            .and_then(|m| m.value_of("output"))
+            .unwrap_or("sound.wav")
+            .to_string();
+        let sound_duration = soundmatches
+            .and_then(|m| m.value_of("duration"))
+            .unwrap_or("180")
+            .parse()?;
+        let sample_rate = soundmatches
+            .and_then(|m| m.value_of("sample_rate"))
+            .map_or(Ok(renderer::DEFAULT_SAMPLE_RATE), str::parse)?;
+        let play_sound = soundmatches.is_some_and(|m| m.is_present("play"));
+
+        // Check to see if they want to render an image.
+        let render_outpath = rendermatches.map(|m| m.value_of("output").unwrap().to_string());
+        let render_time = rendermatches
+            .and_then(|m| m.value_of("time"))
+            .unwrap_or("0")
+            .parse()?;
+        let render_frame = rendermatches
+            .and_then(|m| m.value_of("frame"))
+            .unwrap_or("0")
+            .parse()?;
+        let mouse = rendermatches
+            .or(recordmatches)
+            .and_then(|m| m.value_of("mouse"))
+            .unwrap_or("0,0,0,0");
+        let render_mouse = parse_vec4(mouse).ok_or_else(|| InvalidMouseError::new(mouse))?;
+        let render_date = match rendermatches
+            .or(recordmatches)
+            .and_then(|m| m.value_of("date"))
+        {
+            Some(date) => Some(parse_vec4(date).ok_or_else(|| InvalidDateError::new(date))?),
+            None => None,
+        };
+
+        // Check to see if they want to record frames.
+        let record_outpath = recordmatches.map(|m| m.value_of("output").unwrap().to_string());
+        let record_frames = recordmatches
+            .and_then(|m| m.value_of("frames"))
+            .unwrap_or("300");
+        let record_frames = parse_count("frames", record_frames)?;
+        let record_fps = recordmatches
+            .and_then(|m| m.value_of("fps"))
+            .unwrap_or("60");
+        let record_fps = parse_count("fps", record_fps)?;
+        let record_start = recordmatches
+            .and_then(|m| m.value_of("start"))
+            .unwrap_or("0")
+            .parse()?;
 
         Ok(ArgValues {
             width,
             height,
             shaderpath,
+            commonpath,
+            buffer_a_path,
+            buffer_b_path,
+            buffer_c_path,
+            buffer_d_path,
+            cube_a_path,
+            channel_bindings,
             texture0path,
             texture1path,
             texture2path,
@@ -139,12 +394,192 @@ impl ArgValues {
             filter1,
             filter2,
             filter3,
-            anisotropic_max,
             examplename,
             getid,
+            batch,
             andrun,
+            base_url,
+            api_key,
+            download_rate,
+            download_dir,
+            on_existing,
+            runid,
+            search_terms,
             title,
             force_srgb_off,
+            legacy_mouse,
+            soundpath,
+            sound_outpath,
+            sound_duration,
+            sample_rate,
+            play_sound,
+            render_outpath,
+            render_time,
+            render_frame,
+            render_mouse,
+            render_date,
+            record_outpath,
+            record_frames,
+            record_fps,
+            record_start,
+        })
+    }
+
+    // Downloads shaders from the base URL, with the API key if there is one.
+    pub fn downloader(&self) -> Downloader {
+        Downloader::new(
+            &self.base_url,
+            self.api_key.clone(),
+            self.download_rate,
+            self.download_dir.clone(),
+            self.on_existing,
+        )
+    }
+
+    // Returns the shader path for the given buffer pass, if any.
+    pub fn buffer_path(&self, id: BufferId) -> Option<&String> {
+        match id {
+            BufferId::A => self.buffer_a_path.as_ref(),
+            BufferId::B => self.buffer_b_path.as_ref(),
+            BufferId::C => self.buffer_c_path.as_ref(),
+            BufferId::D => self.buffer_d_path.as_ref(),
+        }
+    }
+
+    // Loads the code shared by every pass (Shadertoy's "Common" tab). Empty if there is none.
+    pub fn common_src(&self) -> error::Result<String> {
+        match self.commonpath {
+            Some(ref commonpath) => loader::read_shader_file(commonpath),
+            None => Ok(String::new()),
+        }
+    }
+
+    // The project manifest given on the command line, if any.
+    fn project(&self) -> error::Result<Option<Project>> {
+        match self.shaderpath {
+            Some(ref path) if self.examplename.is_none() && project::is_manifest(path) => {
+                Ok(Some(Project::load(path)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Paths of every file the shader is loaded from, for reloading when they change.
+    pub fn shader_paths(&self) -> error::Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = self.shaderpath.iter().map(PathBuf::from).collect();
+        match self.project()? {
+            Some(project) => paths.extend(project.source_paths()),
+            None => paths.extend(
+                self.commonpath
+                    .iter()
+                    .chain(BufferId::ALL.iter().filter_map(|&id| self.buffer_path(id)))
+                    .chain(self.cube_a_path.iter())
+                    .map(PathBuf::from),
+            ),
+        }
+        Ok(paths)
+    }
+
+    // Loads the shader given on the command line (or the example or default shader), along with
+    // its common code, buffers, textures and samplers. A project manifest replaces the options for
+    // individual passes and channels.
+    pub fn shader_config(&self) -> error::Result<ShaderConfig> {
+        if let Some(project) = self.project()? {
+            return Ok(ShaderConfig {
+                sample_rate: self.sample_rate,
+                ..project.shader_config()?
+            });
+        }
+
+        let image_src = if let Some(ref example) = self.examplename {
+            loader::example_src(example)?.to_string()
+        } else {
+            // Read fragment shader from file into String buffer.
+            match self.shaderpath {
+                Some(ref shaderpath) => loader::read_shader_file(shaderpath)?,
+                None => String::from(loader::DEFAULT_FRAG_SRC_STR),
+            }
+        };
+
+        let mut buffer_srcs = [None, None, None, None];
+        for &id in BufferId::ALL.iter() {
+            if let Some(path) = self.buffer_path(id) {
+                buffer_srcs[id.index()] = Some(loader::read_shader_file(path)?);
+            }
+        }
+        let cube_src = match self.cube_a_path {
+            Some(ref path) => Some(loader::read_shader_file(path)?),
+            None => None,
+        };
+        let image_path = match self.examplename {
+            Some(ref example) => Some(format!("{}.frag", example)),
+            None => self.shaderpath.clone(),
+        };
+
+        Ok(ShaderConfig {
+            image_src,
+            common_src: self.common_src()?,
+            buffer_srcs,
+            cube_src,
+            image_path,
+            common_path: self.commonpath.clone(),
+            buffer_paths: BufferId::ALL.map(|id| self.buffer_path(id).cloned()),
+            cube_path: self.cube_a_path.clone(),
+            textures: self.textures(),
+            samplers: self.samplers(),
+            channel_bindings: self.channel_bindings.clone(),
+            sample_rate: self.sample_rate,
         })
     }
+
+    // Textures for iChannel0-3: images, or volumes if they're .bin files.
+    pub fn textures(&self) -> Vec<TextureSource> {
+        let texture = |path: &Option<String>, id| match *path {
+            Some(ref path) if path.ends_with(".bin") => {
+                TextureSource::Volume(VolumeSource::Bin(path.clone()))
+            }
+            Some(ref path) => TextureSource::File {
+                path: path.clone(),
+                vflip: true,
+                srgb: false,
+            },
+            None => TextureSource::Default(id),
+        };
+        vec![
+            texture(&self.texture0path, TextureId::Zero),
+            texture(&self.texture1path, TextureId::One),
+            texture(&self.texture2path, TextureId::Two),
+            texture(&self.texture3path, TextureId::Three),
+        ]
+    }
+
+    pub fn samplers(&self) -> [SamplerInfo; 4] {
+        [
+            SamplerInfo::new(self.filter0, self.wrap0),
+            SamplerInfo::new(self.filter1, self.wrap1),
+            SamplerInfo::new(self.filter2, self.wrap2),
+            SamplerInfo::new(self.filter3, self.wrap3),
+        ]
+    }
+}
+
+// Parses the value of an option that must be a positive integer.
+fn parse_count(option: &str, value: &str) -> error::Result<u32> {
+    match value.parse()? {
+        0 => Err(ZeroCountError::new(option).into()),
+        count => Ok(count),
+    }
+}
+
+// Parses four comma-separated floats, e.g. "1,2,3,4".
+fn parse_vec4(value: &str) -> Option<[f32; 4]> {
+    let values: Vec<f32> = value
+        .split(',')
+        .map(|value| value.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match values[..] {
+        [x, y, z, w] => Some([x, y, z, w]),
+        _ => None,
+    }
 }
@@ -0,0 +1,218 @@
+use argvalues::ArgValues;
+use download::{self, Downloader, Query, Saved};
+use error::{self, BatchDownloadError, ReadIdListError};
+use schema::Shader;
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Shaders to download with a single `get`, from any mix of ids, a file of ids and an API query.
+pub struct Batch {
+    // Shader ids or URLs.
+    pub ids: Vec<String>,
+
+    // File of more ids or URLs, one per line.
+    pub file: Option<String>,
+
+    // Search for more shaders through the API.
+    pub query: Option<Query>,
+
+    // Only shaders with all these tags are saved.
+    pub tags: Vec<String>,
+
+    // Number of shaders downloaded at once.
+    pub jobs: usize,
+}
+
+impl Batch {
+    // True if it's just one shader, which can be run once downloaded.
+    pub fn is_single(&self) -> bool {
+        self.ids.len() == 1 && self.file.is_none() && self.query.is_none() && self.tags.is_empty()
+    }
+
+    // Ids of every shader to download, without duplicates.
+    fn collect_ids(&self, downloader: &Downloader) -> error::Result<Vec<String>> {
+        let mut ids = self.ids.clone();
+        if let Some(ref file) = self.file {
+            let contents =
+                fs::read_to_string(file).map_err(|err| ReadIdListError::new(file, err))?;
+            ids.extend(
+                contents
+                    .lines()
+                    .map(|line| line.split('#').next().unwrap_or("").trim())
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.to_string()),
+            );
+        }
+        if let Some(ref query) = self.query {
+            let found = downloader.query(query)?;
+            info!("Query found {} shaders", found.len());
+            ids.extend(found);
+        }
+
+        let mut unique: Vec<String> = Vec::new();
+        for id in ids {
+            let id = download::parse_id(&id).to_string();
+            if !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+        Ok(unique)
+    }
+
+    // True if the shader has all the tags (ignoring case).
+    fn has_tags(&self, shader: &Shader) -> bool {
+        let tags: Vec<String> = shader
+            .info
+            .tags
+            .iter()
+            .map(|tag| tag.to_lowercase())
+            .collect();
+        self.tags
+            .iter()
+            .all(|tag| tags.contains(&tag.to_lowercase()))
+    }
+}
+
+// What happened to each shader in a batch.
+enum Outcome {
+    // Saved to the manifest at the path.
+    Saved(String),
+
+    // Downloaded but not saved, for the reason given.
+    Skipped(String),
+
+    Failed(String),
+}
+
+// Downloads every shader in the batch, a few at a time, then prints what happened to each. Fails
+// if any of them couldn't be downloaded.
+pub fn get(av: &ArgValues, batch: &Batch) -> error::Result<()> {
+    let downloader = av.downloader();
+    let ids = batch.collect_ids(&downloader)?;
+    if ids.is_empty() {
+        println!("No shaders to download");
+        return Ok(());
+    }
+    if av.andrun {
+        warn!("Not running any shader, since there are several");
+    }
+
+    let download = |id: &str| -> Outcome {
+        if let Some(path) = downloader.existing_project(id) {
+            return Outcome::Skipped(format!("already saved to {}", path));
+        }
+        let shader = match downloader.fetch(id) {
+            Ok(shader) => shader,
+            Err(err) => return Outcome::Failed(err.to_string()),
+        };
+        if !batch.has_tags(&shader) {
+            return Outcome::Skipped("doesn't have all the tags".to_string());
+        }
+        match downloader.save(&shader) {
+            Ok(Saved::New(path)) => Outcome::Saved(path),
+            Ok(Saved::Existing(path)) => Outcome::Skipped(format!("already saved to {}", path)),
+            Err(err) => Outcome::Failed(err.to_string()),
+        }
+    };
+
+    // Each thread takes the next shader nobody has started on until there are none left.
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..batch.jobs.clamp(1, ids.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= ids.len() {
+                    break;
+                }
+                let outcome = download(&ids[i]);
+                outcomes.lock().unwrap().push((i, outcome));
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|&(i, _)| i);
+
+    let (mut saved, mut skipped, mut failed) = (0, 0, 0);
+    for (i, outcome) in outcomes {
+        let id = &ids[i];
+        match outcome {
+            Outcome::Saved(path) => {
+                saved += 1;
+                println!("saved    {}  {}", id, path);
+            }
+            Outcome::Skipped(reason) => {
+                skipped += 1;
+                println!("skipped  {}  {}", id, reason);
+            }
+            Outcome::Failed(err) => {
+                failed += 1;
+                println!("failed   {}  {}", id, err);
+            }
+        }
+    }
+    println!("{} saved, {} skipped, {} failed", saved, skipped, failed);
+
+    if failed > 0 {
+        return Err(BatchDownloadError::new(failed, ids.len()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use download::OnExisting;
+
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn batch(ids: &[&str], file: Option<String>) -> Batch {
+        Batch {
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            file,
+            query: None,
+            tags: Vec::new(),
+            jobs: 1,
+        }
+    }
+
+    fn collect_ids(batch: &Batch) -> error::Result<Vec<String>> {
+        let downloader = Downloader::new("", None, 0.0, PathBuf::new(), OnExisting::Skip);
+        batch.collect_ids(&downloader)
+    }
+
+    #[test]
+    fn collects_ids() {
+        let path = env::temp_dir().join(format!("shadertoy_rs_{}_ids.txt", process::id()));
+        let list = "# Favorites\n\
+                    XdXGzn\n\
+                    \n\
+                    \x20  https://www.shadertoy.com/view/4dfGRr   # Seascape again\n\
+                    www.shadertoy.com/view/MdX3Rr?foo\n\
+                    #XXXXXX\n\
+                    \t\n\
+                    lsX3W4 # last\n";
+        fs::write(&path, list).unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let ids = collect_ids(&batch(
+            &["4dfGRr", "https://www.shadertoy.com/view/XdXGzn/"],
+            Some(path.clone()),
+        ));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ids.unwrap(), ["4dfGRr", "XdXGzn", "MdX3Rr", "lsX3W4"]);
+    }
+
+    #[test]
+    fn fails_without_id_list() {
+        let err = collect_ids(&batch(&[], Some("no such list.txt".to_string()))).unwrap_err();
+        assert!(err.to_string().contains("no such list.txt"), "{}", err);
+        assert!(collect_ids(&batch(&[], None)).unwrap().is_empty());
+    }
+}
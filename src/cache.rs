@@ -0,0 +1,191 @@
+use download;
+use error::{self, InvalidShaderIdError, NotCachedError, SaveShaderError};
+use schema::Info;
+
+use chrono::{TimeZone, Utc};
+use dirs;
+use serde_json::{self, Value};
+use sha2::{Digest, Sha256};
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Per-user cache directory, e.g. ~/.cache/shadertoy-rs on Linux. SHADERTOY_CACHE_DIR overrides it.
+pub fn cache_dir() -> PathBuf {
+    match env::var_os("SHADERTOY_CACHE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::cache_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join("shadertoy-rs"),
+    }
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Media files (textures, cubemaps, volumes and music) referenced by downloaded shaders. Files are
+// stored by the SHA-256 of their contents, so each is only kept once no matter how many shaders
+// use it, along with a small file per URL naming the content it was last fetched as.
+pub struct MediaCache {
+    dir: PathBuf,
+}
+
+impl MediaCache {
+    pub fn new() -> MediaCache {
+        MediaCache {
+            dir: cache_dir().join("media"),
+        }
+    }
+
+    fn url_path(&self, url: &str) -> PathBuf {
+        self.dir.join("urls").join(hex_digest(url.as_bytes()))
+    }
+
+    // Returns the cached file for the URL, if it has been fetched before.
+    pub fn get(&self, url: &str) -> Option<PathBuf> {
+        let name = fs::read_to_string(self.url_path(url)).ok()?;
+        let path = self.dir.join(name.trim());
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    // Stores the contents fetched from the URL, returning the path of the cached file.
+    pub fn insert(&self, url: &str, contents: &[u8]) -> error::Result<PathBuf> {
+        let extension = Path::new(url)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or("bin");
+        let name = format!("{}.{}", hex_digest(contents), extension);
+
+        let path = self.dir.join(&name);
+        let url_path = self.url_path(url);
+        fs::create_dir_all(url_path.parent().unwrap())
+            .and_then(|_| fs::write(&path, contents))
+            .and_then(|_| fs::write(&url_path, &name))
+            .map_err(|err| SaveShaderError::new(&path.to_string_lossy(), err))?;
+        Ok(path)
+    }
+}
+
+// What `list` and `search` show about a cached shader.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShaderMeta {
+    pub id: String,
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    pub tags: Vec<String>,
+
+    // Date the shader was published, as YYYY-MM-DD.
+    pub date: Option<String>,
+
+    // When the shader was last downloaded, in RFC 3339 format.
+    pub downloaded: String,
+}
+
+impl ShaderMeta {
+    fn from_info(info: &Info) -> ShaderMeta {
+        // Dates are seconds since the epoch, as a string.
+        let date = info
+            .date
+            .as_ref()
+            .and_then(|date| date.to_string().parse().ok())
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .map(|date| date.format("%Y-%m-%d").to_string());
+
+        ShaderMeta {
+            id: info.id.clone(),
+            name: info.name.clone(),
+            author: info.username.clone(),
+            description: info.description.clone(),
+            tags: info.tags.clone(),
+            date,
+            downloaded: Utc::now().to_rfc3339(),
+        }
+    }
+
+    // True if every term appears (ignoring case) in the id, name, author, description or tags.
+    pub fn matches(&self, terms: &[String]) -> bool {
+        let haystack = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.id,
+            self.name,
+            self.author,
+            self.description,
+            self.tags.join("\n")
+        )
+        .to_lowercase();
+        terms
+            .iter()
+            .all(|term| haystack.contains(&term.to_lowercase()))
+    }
+}
+
+// Downloaded shaders, so they can be browsed and run again without network access. Each shader
+// gets a directory named after its id, holding the JSON from shadertoy.com, its metadata, and the
+// project it was last run from.
+pub struct ShaderCache {
+    dir: PathBuf,
+}
+
+impl ShaderCache {
+    pub fn new() -> ShaderCache {
+        ShaderCache {
+            dir: cache_dir().join("shaders"),
+        }
+    }
+
+    fn shader_dir(&self, id: &str) -> error::Result<PathBuf> {
+        // Ids end up in paths, so only allow what shadertoy.com uses.
+        if !download::is_valid_id(id) {
+            return Err(InvalidShaderIdError::new(id).into());
+        }
+        Ok(self.dir.join(id))
+    }
+
+    // Directory to save the cached shader's project to.
+    pub fn project_dir(&self, id: &str) -> error::Result<PathBuf> {
+        Ok(self.shader_dir(id)?.join("project"))
+    }
+
+    // Stores the shader's JSON along with its metadata.
+    pub fn insert(&self, info: &Info, shader: &Value) -> error::Result<()> {
+        let dir = self.shader_dir(&info.id)?;
+        let meta = ShaderMeta::from_info(info);
+        fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(dir.join("shader.json"), shader.to_string()))
+            .and_then(|_| fs::write(dir.join("meta.json"), serde_json::to_string(&meta)?))
+            .map_err(|err| SaveShaderError::new(&dir.to_string_lossy(), err))?;
+        Ok(())
+    }
+
+    // Returns the cached JSON of the shader.
+    pub fn get(&self, id: &str) -> error::Result<Value> {
+        let path = self.shader_dir(id)?.join("shader.json");
+        let json = fs::read_to_string(path).map_err(|_| NotCachedError::new(id))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // Metadata of every cached shader, sorted by name.
+    pub fn list(&self) -> Vec<ShaderMeta> {
+        let mut shaders: Vec<ShaderMeta> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let meta = fs::read_to_string(entry.ok()?.path().join("meta.json")).ok()?;
+                serde_json::from_str(&meta).ok()
+            })
+            .collect();
+        shaders.sort_by_key(|meta| meta.name.to_lowercase());
+        shaders
+    }
+}
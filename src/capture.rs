@@ -0,0 +1,331 @@
+use argvalues::ArgValues;
+use error::{self, SaveImageError, SaveVideoError, UnsupportedImageFormatError};
+use gfx;
+use gfx::format::{Formatted, Rgba32F, Rgba8};
+use gfx::memory::Typed;
+use gfx::texture;
+use headless::{Encoder, Headless, ReadbackTarget};
+use renderer::{Renderer, ShaderConfig};
+
+use exr;
+use image;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Image formats the render subcommand can write, chosen by file extension.
+enum ImageFormat {
+    // 8-bit sRGB, as displayed in a window.
+    Png,
+
+    // 32-bit float, with no clamping of the shader's output.
+    Exr,
+}
+
+impl ImageFormat {
+    fn from_path(path: &str) -> error::Result<ImageFormat> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("exr") => Ok(ImageFormat::Exr),
+            _ => Err(UnsupportedImageFormatError::new(path).into()),
+        }
+    }
+}
+
+// Renders a single frame of the shader offscreen and saves it to a PNG or EXR image.
+pub fn render(av: &ArgValues, outpath: &str) -> error::Result<()> {
+    let mut headless = Headless::new()?;
+    let mut encoder = headless.create_encoder();
+
+    let config = av.shader_config()?;
+    let (width, height) = (av.width as texture::Size, av.height as texture::Size);
+
+    match ImageFormat::from_path(outpath)? {
+        ImageFormat::Png => {
+            let target = headless.create_readback_target::<Rgba8>(width, height)?;
+            render_frame(&mut headless, &mut encoder, av, &config, &target)?;
+            let pixels: Vec<[u8; 4]> = headless.read_pixels(&mut encoder, &target)?;
+            save_png(outpath, &pixels, width, height)
+        }
+        ImageFormat::Exr => {
+            let target = headless.create_readback_target::<Rgba32F>(width, height)?;
+            render_frame(&mut headless, &mut encoder, av, &config, &target)?;
+            let pixels: Vec<[f32; 4]> = headless.read_pixels(&mut encoder, &target)?;
+            save_exr(outpath, &pixels, width, height)
+        }
+    }
+}
+
+// Renders frame av.render_frame at time av.render_time into the target.
+fn render_frame<T>(
+    headless: &mut Headless,
+    encoder: &mut Encoder,
+    av: &ArgValues,
+    config: &ShaderConfig,
+    target: &ReadbackTarget<T>,
+) -> error::Result<()>
+where
+    T: gfx::format::RenderFormat + gfx::format::TextureFormat,
+{
+    let target = target.target.raw();
+    let mut renderer = Renderer::new(
+        &mut headless.factory,
+        encoder,
+        config,
+        T::get_format(),
+        av.width,
+        av.height,
+        target,
+    )?;
+    renderer.set_mouse(av.render_mouse);
+    renderer.set_date(av.render_date);
+
+    // Buffers carry state from frame to frame, so every earlier frame has to be rendered too. Those
+    // frames are spaced evenly in time, ending at the requested time.
+    let (last, time) = (av.render_frame, av.render_time);
+    let time_delta = if last > 0 { time / last as f32 } else { 0.0 };
+    let first = if renderer.has_buffers() { 0 } else { last };
+
+    renderer.set_frame(first);
+    for frame in first..=last {
+        renderer.set_time(time - (last - frame) as f32 * time_delta, time_delta);
+        renderer.render(encoder, target);
+    }
+
+    Ok(())
+}
+
+// Pixels are read back bottom row first, while images are stored top row first.
+fn flip_rows(pixels: &[[u8; 4]], width: texture::Size) -> Vec<u8> {
+    pixels
+        .chunks(usize::from(width))
+        .rev()
+        .flat_map(|row| row.iter().flat_map(|pixel| pixel.iter().cloned()))
+        .collect()
+}
+
+fn save_png(
+    path: &str,
+    pixels: &[[u8; 4]],
+    width: texture::Size,
+    height: texture::Size,
+) -> error::Result<()> {
+    image::save_buffer(
+        path,
+        &flip_rows(pixels, width),
+        u32::from(width),
+        u32::from(height),
+        image::ColorType::RGBA(8),
+    )
+    .map_err(|err| SaveImageError::new(path, err.to_string()))?;
+    Ok(())
+}
+
+fn save_exr(
+    path: &str,
+    pixels: &[[f32; 4]],
+    width: texture::Size,
+    height: texture::Size,
+) -> error::Result<()> {
+    let (width, height) = (usize::from(width), usize::from(height));
+    exr::prelude::write_rgba_file(path, width, height, |x, y| {
+        let pixel = pixels[(height - 1 - y) * width + x];
+        (pixel[0], pixel[1], pixel[2], pixel[3])
+    })
+    .map_err(|err| SaveImageError::new(path, err.to_string()))?;
+    Ok(())
+}
+
+// Where the record subcommand writes its frames.
+enum RecordOutput {
+    // Numbered PNG files, named by substituting the frame number into a pattern.
+    Png(String),
+
+    // A single uncompressed YUV4MPEG2 stream.
+    Y4m(Box<dyn Write>),
+}
+
+impl RecordOutput {
+    // "-" and *.y4m paths get a Y4M stream (on stdout for "-"), anything else numbered PNGs.
+    fn from_path(path: &str) -> error::Result<RecordOutput> {
+        if path == "-" {
+            return Ok(RecordOutput::Y4m(Box::new(BufWriter::new(io::stdout()))));
+        }
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("y4m") => {
+                let file = File::create(path).map_err(|err| SaveVideoError::new(path, err))?;
+                Ok(RecordOutput::Y4m(Box::new(BufWriter::new(file))))
+            }
+            Some("png") => Ok(RecordOutput::Png(path.to_string())),
+            _ => Err(UnsupportedImageFormatError::new(path).into()),
+        }
+    }
+}
+
+// Renders av.record_frames frames at a fixed timestep of 1 / av.record_fps seconds, starting at
+// av.record_start, and writes them as numbered PNGs or a Y4M stream.
+pub fn record(av: &ArgValues, outpath: &str) -> error::Result<()> {
+    let mut output = RecordOutput::from_path(outpath)?;
+
+    let mut headless = Headless::new()?;
+    let mut encoder = headless.create_encoder();
+
+    let config = av.shader_config()?;
+    let (width, height) = (av.width as texture::Size, av.height as texture::Size);
+
+    let target = headless.create_readback_target::<Rgba8>(width, height)?;
+    let raw_target = target.target.raw();
+    let mut renderer = Renderer::new(
+        &mut headless.factory,
+        &mut encoder,
+        &config,
+        Rgba8::get_format(),
+        av.width,
+        av.height,
+        raw_target,
+    )?;
+    renderer.set_mouse(av.render_mouse);
+    renderer.set_date(av.render_date);
+
+    if let RecordOutput::Y4m(ref mut writer) = output {
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+            width, height, av.record_fps
+        )
+        .map_err(|err| SaveVideoError::new(outpath, err))?;
+    }
+
+    let time_delta = 1.0 / av.record_fps as f32;
+    for frame in 0..av.record_frames {
+        renderer.set_time(av.record_start + frame as f32 * time_delta, time_delta);
+        renderer.render(&mut encoder, raw_target);
+        let pixels: Vec<[u8; 4]> = headless.read_pixels(&mut encoder, &target)?;
+
+        match output {
+            RecordOutput::Png(ref pattern) => {
+                save_png(&frame_path(pattern, frame), &pixels, width, height)?
+            }
+            RecordOutput::Y4m(ref mut writer) => write_y4m_frame(writer, &pixels, width)
+                .map_err(|err| SaveVideoError::new(outpath, err))?,
+        }
+        info!("Recorded frame {}/{}", frame + 1, av.record_frames);
+    }
+
+    if let RecordOutput::Y4m(ref mut writer) = output {
+        writer
+            .flush()
+            .map_err(|err| SaveVideoError::new(outpath, err))?;
+    }
+
+    Ok(())
+}
+
+// Substitutes the frame number into a printf-style pattern such as "frame_%05d.png". Patterns
+// without one get the (zero-padded) number appended to the file stem, e.g. "clip_00042.png".
+fn frame_path(pattern: &str, frame: u32) -> String {
+    if let Some(start) = pattern.find('%') {
+        let spec = &pattern[start + 1..];
+        if let Some(end) = spec.find('d') {
+            if spec[..end].chars().all(|c| c.is_ascii_digit()) {
+                let width = spec[..end].parse().unwrap_or(0);
+                return format!(
+                    "{}{:0width$}{}",
+                    &pattern[..start],
+                    frame,
+                    &spec[end + 1..],
+                    width = width
+                );
+            }
+        }
+    }
+
+    let path = Path::new(pattern);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let file_name = format!("{}_{:05}.png", stem, frame);
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Writes one frame of a 4:4:4 Y4M stream, converting to studio-range BT.601 YCbCr (what players
+// assume for Y4M without colorspace tags).
+fn write_y4m_frame<W: Write + ?Sized>(
+    writer: &mut W,
+    pixels: &[[u8; 4]],
+    width: texture::Size,
+) -> io::Result<()> {
+    let rgb = flip_rows(pixels, width);
+    let num_pixels = rgb.len() / 4;
+    let mut planes = vec![0u8; num_pixels * 3];
+    for (i, pixel) in rgb.chunks(4).enumerate() {
+        let (r, g, b) = (
+            f32::from(pixel[0]),
+            f32::from(pixel[1]),
+            f32::from(pixel[2]),
+        );
+        let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+        let cb = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+        let cr = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+        planes[i] = y.round() as u8;
+        planes[num_pixels + i] = cb.round() as u8;
+        planes[2 * num_pixels + i] = cr.round() as u8;
+    }
+
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&planes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_frame_numbers() {
+        assert_eq!(frame_path("frame_%05d.png", 42), "frame_00042.png");
+        assert_eq!(frame_path("frame_%d.png", 42), "frame_42.png");
+        assert_eq!(frame_path("frame_%2d.png", 123), "frame_123.png");
+        assert_eq!(frame_path("out/%03d_frame.png", 7), "out/007_frame.png");
+    }
+
+    #[test]
+    fn appends_frame_numbers() {
+        assert_eq!(frame_path("clip.png", 42), "clip_00042.png");
+        assert_eq!(frame_path("out/clip.png", 0), "out/clip_00000.png");
+        assert_eq!(frame_path("100%.png", 1), "100%_00001.png");
+        assert_eq!(frame_path("%xd.png", 1), "%xd_00001.png");
+    }
+
+    #[test]
+    fn writes_y4m_frames() {
+        // Read back bottom row first: white and black below red and blue.
+        let pixels = [
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+            [255, 0, 0, 255],
+            [0, 0, 255, 255],
+        ];
+        let mut stream = Vec::new();
+        write_y4m_frame(&mut stream, &pixels, 2).unwrap();
+
+        let (header, planes) = stream.split_at(6);
+        assert_eq!(header, b"FRAME\n");
+        assert_eq!(
+            planes,
+            [
+                82, 41, 235, 16, // Y
+                90, 240, 128, 128, // Cb
+                240, 110, 128, 128, // Cr
+            ]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use error::{self, InvalidConfigError};
+
+use dirs;
+use toml;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// User settings, read from config.toml in the per-user config directory (e.g.
+// ~/.config/shadertoy-rs/config.toml on Linux, or $SHADERTOY_CONFIG):
+//
+//     api_key = "..."
+//     base_url = "https://www.shadertoy.com"
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // Key for the Shadertoy API, from https://www.shadertoy.com/myapps.
+    pub api_key: Option<String>,
+
+    // Where to download shaders from.
+    pub base_url: Option<String>,
+}
+
+impl Config {
+    fn path() -> Option<PathBuf> {
+        match env::var_os("SHADERTOY_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => dirs::config_dir().map(|dir| dir.join("shadertoy-rs").join("config.toml")),
+        }
+    }
+
+    // Loads the config file. A missing file is the same as an empty one.
+    pub fn load() -> error::Result<Config> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        let invalid = |err: String| InvalidConfigError::new(&path.to_string_lossy(), err);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(toml::from_str(&contents).map_err(|err| invalid(err.to_string()))?),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(invalid(err.to_string()).into()),
+        }
+    }
+}
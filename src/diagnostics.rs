@@ -0,0 +1,317 @@
+// Compiler errors reported against the user's sources, in the style of rustc, rather than as the
+// driver's info log for the whole fragment shader (which also has generated code before and after
+// the user's, throwing off its line numbers).
+
+use gfx;
+use gfx::shade::core::CreateShaderError;
+use gfx::shade::ProgramError;
+use glsl;
+
+use std::fmt::Write;
+use std::iter;
+
+// A fragment shader assembled from generated code and the user's sources, remembering which lines
+// came from where.
+pub struct ComposedSource {
+    code: String,
+    segments: Vec<Segment>,
+}
+
+struct Segment {
+    // Name of the file (or pass) the code came from. None for generated code.
+    name: Option<String>,
+
+    // Line of the composed source the segment starts at, counting from 1.
+    first_line: usize,
+
+    // The code as written, and as compiled.
+    original: String,
+    translated: String,
+}
+
+// A message from the driver's info log.
+struct Message<'a> {
+    severity: &'a str,
+    line: usize,
+    column: Option<usize>,
+    text: &'a str,
+}
+
+impl ComposedSource {
+    pub fn new() -> ComposedSource {
+        ComposedSource {
+            code: String::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    // Appends generated code, which isn't translated.
+    pub fn push_generated(&mut self, code: &str) {
+        self.push(None, code.to_string(), code.to_string());
+    }
+
+    // Appends one of the user's sources, translated from GLSL ES.
+    pub fn push_source(&mut self, name: &str, src: &str) {
+        self.push(
+            Some(name.to_string()),
+            src.to_string(),
+            glsl::translate(src),
+        );
+    }
+
+    fn push(&mut self, name: Option<String>, original: String, translated: String) {
+        let first_line = self.code.matches('\n').count() + 1;
+        self.code.push_str(&translated);
+        self.code.push('\n');
+        self.segments.push(Segment {
+            name,
+            first_line,
+            original,
+            translated,
+        });
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.code.as_bytes()
+    }
+
+    // Describes an error creating a pipeline from the source, with the messages of a failed
+    // compilation pointing at the user's code.
+    pub fn explain(&self, err: &gfx::PipelineStateError<String>) -> String {
+        match *err {
+            gfx::PipelineStateError::Program(ProgramError::Pixel(
+                CreateShaderError::CompilationFailed(ref log),
+            )) => self.diagnose(log),
+            ref err => err.to_string(),
+        }
+    }
+
+    // Rewrites the driver's info log as rustc-style diagnostics, each with the line it's about.
+    // Lines of the log that can't be parsed are kept as they are.
+    pub fn diagnose(&self, log: &str) -> String {
+        let mut out = String::new();
+        for line in log.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match parse_message(line) {
+                Some(message) => self.write_message(&mut out, &message),
+                None => {
+                    let _ = writeln!(out, "{}", line);
+                }
+            }
+        }
+        out.trim_end().to_string()
+    }
+
+    fn write_message(&self, out: &mut String, message: &Message) {
+        let _ = writeln!(out, "{}: {}", message.severity, message.text);
+
+        let segment = match self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.first_line <= message.line)
+        {
+            Some(segment) => segment,
+            None => return,
+        };
+        let line = message.line - segment.first_line + 1;
+        let name = segment.name.as_deref().unwrap_or("<generated>");
+
+        // Show the code as written. Translation can move things around within a line, so the
+        // column is only trusted if the line wasn't changed.
+        let original = segment.original.lines().nth(line - 1).unwrap_or("");
+        let translated = segment.translated.lines().nth(line - 1).unwrap_or("");
+        let column = message
+            .column
+            .filter(|_| original == translated)
+            .and_then(|column| expand_column(original, column));
+
+        let gutter = " ".repeat(line.to_string().len());
+        match column {
+            Some(column) => {
+                let _ = writeln!(out, "{}--> {}:{}:{}", gutter, name, line, column);
+            }
+            None => {
+                let _ = writeln!(out, "{}--> {}:{}", gutter, name, line);
+            }
+        }
+        let _ = writeln!(out, "{} |", gutter);
+        let _ = writeln!(out, "{} | {}", line, original);
+
+        // Point at the column, or underline the whole line if there isn't one. Tabs before the
+        // marker are kept so it lines up with the snippet however wide they're shown.
+        let marker = match column {
+            Some(column) => format!("{}^", padding(original, column - 1)),
+            None => {
+                let indent = original.chars().count() - original.trim_start().chars().count();
+                format!(
+                    "{}{}",
+                    padding(original, indent),
+                    "^".repeat(original.trim().chars().count().max(1))
+                )
+            }
+        };
+        let _ = writeln!(out, "{} | {}", gutter, marker);
+        let _ = writeln!(out);
+    }
+}
+
+impl Default for ComposedSource {
+    fn default() -> ComposedSource {
+        ComposedSource::new()
+    }
+}
+
+// Blanks out the first `len` characters of a line, keeping tabs as they are.
+fn padding(line: &str, len: usize) -> String {
+    line.chars()
+        .chain(iter::repeat(' '))
+        .take(len)
+        .map(|c| if c == '\t' { c } else { ' ' })
+        .collect()
+}
+
+// Mesa counts columns after its preprocessor has collapsed each run of whitespace into a single
+// space. Finds the column of the line as written that a collapsed column refers to.
+fn expand_column(line: &str, column: usize) -> Option<usize> {
+    let mut collapsed = 0;
+    let mut in_whitespace = false;
+    for (i, c) in line.char_indices() {
+        if !(c.is_whitespace() && in_whitespace) {
+            collapsed += 1;
+        }
+        in_whitespace = c.is_whitespace();
+        if collapsed == column {
+            return Some(line[..i].chars().count() + 1);
+        }
+    }
+    None
+}
+
+// Parses a message from an info log, in any of the formats drivers use:
+//
+//     0:12(5): error: ...           (Mesa)
+//     0(12) : error C1008: ...      (NVIDIA)
+//     ERROR: 0:12: ...              (AMD, Intel, Apple)
+fn parse_message(line: &str) -> Option<Message<'_>> {
+    let number = |s: &str| s.trim().parse::<usize>().ok();
+
+    // Mesa.
+    if let Some((location, rest)) = line.split_once("): ") {
+        if let Some((position, column)) = location.split_once('(') {
+            if let Some((_, line)) = position.split_once(':') {
+                if let (Some(line), Some(column)) = (number(line), number(column)) {
+                    let (severity, text) = rest.split_once(": ")?;
+                    return Some(Message {
+                        severity: severity.trim_start_matches("preprocessor "),
+                        line,
+                        column: Some(column),
+                        text,
+                    });
+                }
+            }
+        }
+    }
+
+    // NVIDIA.
+    if let Some((location, rest)) = line.split_once(") : ") {
+        if let Some((_, line)) = location.split_once('(') {
+            if let Some(line) = number(line) {
+                let (severity, text) = rest.split_once(": ")?;
+                return Some(Message {
+                    severity: severity.split_whitespace().next()?,
+                    line,
+                    column: None,
+                    text,
+                });
+            }
+        }
+    }
+
+    // AMD, Intel and Apple.
+    let (severity, rest) = line.split_once(": ")?;
+    if severity == "ERROR" || severity == "WARNING" {
+        let mut parts = rest.splitn(3, ':');
+        let (_, line, text) = (parts.next()?, number(parts.next()?)?, parts.next()?);
+        return Some(Message {
+            severity: if severity == "ERROR" {
+                "error"
+            } else {
+                "warning"
+            },
+            line,
+            column: None,
+            text: text.trim(),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mesa_messages() {
+        let message = parse_message("0:12(5): error: `oops' undeclared").unwrap();
+        assert_eq!(message.severity, "error");
+        assert_eq!(message.line, 12);
+        assert_eq!(message.column, Some(5));
+        assert_eq!(message.text, "`oops' undeclared");
+
+        let message = parse_message("0:3(1): preprocessor error: syntax error").unwrap();
+        assert_eq!(message.severity, "error");
+        assert_eq!(message.line, 3);
+    }
+
+    #[test]
+    fn parses_nvidia_messages() {
+        let message = parse_message("0(12) : error C1008: undefined variable \"oops\"").unwrap();
+        assert_eq!(message.severity, "error");
+        assert_eq!(message.line, 12);
+        assert_eq!(message.column, None);
+        assert_eq!(message.text, "undefined variable \"oops\"");
+    }
+
+    #[test]
+    fn parses_amd_intel_and_apple_messages() {
+        let message = parse_message("ERROR: 0:12: 'oops' : undeclared identifier").unwrap();
+        assert_eq!(message.severity, "error");
+        assert_eq!(message.line, 12);
+        assert_eq!(message.column, None);
+        assert_eq!(message.text, "'oops' : undeclared identifier");
+
+        let message = parse_message("WARNING: 0:7: unused variable").unwrap();
+        assert_eq!(message.severity, "warning");
+        assert_eq!(message.line, 7);
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        assert!(parse_message("Compilation failed.").is_none());
+        assert!(parse_message("").is_none());
+    }
+
+    #[test]
+    fn points_carets_past_tabs() {
+        let mut composed = ComposedSource::new();
+        composed.push_source("a.glsl", "void f() {\n\tx = oops;\n}");
+
+        // Mesa collapses the tab to a single space, so "oops" starts at column 6.
+        let diagnostic = composed.diagnose("0:2(6): error: `oops' undeclared");
+        let lines: Vec<&str> = diagnostic.lines().collect();
+        assert_eq!(lines[1], " --> a.glsl:2:6");
+        assert_eq!(lines[3], "2 | \tx = oops;");
+        assert_eq!(lines[4], "  | \t    ^");
+    }
+
+    #[test]
+    fn underlines_tab_indented_lines() {
+        let mut composed = ComposedSource::new();
+        composed.push_source("a.glsl", "void f() {\n\t\tx = oops;\n}");
+
+        let diagnostic = composed.diagnose("0(2) : error C1008: undefined variable \"oops\"");
+        let lines: Vec<&str> = diagnostic.lines().collect();
+        assert_eq!(lines[1], " --> a.glsl:2");
+        assert_eq!(lines[4], "  | \t\t^^^^^^^^^");
+    }
+}
@@ -1,88 +1,953 @@
-use error::{self, InvalidShaderIdError, SaveShaderError};
+use cache::{MediaCache, ShaderCache};
+use error::{
+    self, DownloadMediaError, HttpStatusError, InvalidShaderIdError, MalformedResponseError,
+    PrivateShaderError, SaveShaderError, ShadertoyApiError,
+};
+use pass::BufferId;
+use project::{ChannelManifest, Filter, Manifest, PassManifest, Project, Wrap};
+use schema::{self, Input, QueryResponse, RenderPass, Shader, ShaderResponse};
 
+use serde::de::DeserializeOwned;
 use serde_json::{self, Value};
 
-use reqwest::Client;
+use reqwest::{Client, IntoUrl, Response, Url};
 
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::cmp::max;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn download(id: &str) -> error::Result<(String, String)> {
-    let (name, code) = get_shader_name_and_code(id)?;
+pub const DEFAULT_BASE_URL: &str = "https://www.shadertoy.com";
 
-    File::create(&name)
-        .or_else(|err| return_save_shader_error(&name, err))?
-        .write_all(code.as_bytes())
-        .or_else(|err| return_save_shader_error(&name, err))?;
+// Longest part of a project's directory name taken from the shader's name.
+const MAX_NAME_LEN: usize = 48;
 
-    Ok((name, code))
+// Where shaders are downloaded from, and how. Requests are spaced out to stay under a rate limit,
+// so one Downloader can be shared by several threads downloading at once.
+pub struct Downloader {
+    // Normally DEFAULT_BASE_URL, but can point to a mirror (or a mock server).
+    base_url: String,
+
+    // Key for the Shadertoy API. None to use the site's own endpoint.
+    api_key: Option<String>,
+
+    client: Client,
+
+    // Directory projects are saved in, and what to do if a project is already there.
+    output_dir: PathBuf,
+    on_existing: OnExisting,
+
+    // Minimum time between requests, and when the next one may start.
+    interval: Duration,
+    next_request: Mutex<Instant>,
 }
 
-fn return_save_shader_error<E>(name: &str, err: io::Error) -> error::Result<E> {
-    Err(SaveShaderError::new(name, err).into())
+impl Downloader {
+    // Makes at most rate requests per second, or as many as it can if rate is 0.
+    pub fn new(
+        base_url: &str,
+        api_key: Option<String>,
+        rate: f32,
+        output_dir: PathBuf,
+        on_existing: OnExisting,
+    ) -> Downloader {
+        let interval = if rate > 0.0 {
+            Duration::from_secs_f32(1.0 / rate)
+        } else {
+            Duration::from_secs(0)
+        };
+        Downloader {
+            base_url: base_url.to_string(),
+            api_key,
+            client: Client::new(),
+            output_dir,
+            on_existing,
+            interval,
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    // Blocks until the rate limit allows another request.
+    fn wait(&self) {
+        let start = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let start = max(*next_request, Instant::now());
+            *next_request = start + self.interval;
+            start
+        };
+        let now = Instant::now();
+        if start > now {
+            thread::sleep(start - now);
+        }
+    }
+
+    // Downloads a shader and saves it as a project: a directory named after the shader and its
+    // id, holding the code of each pass, the media files its channels read and a manifest wiring
+    // them together. The shader is also added to the cache. Returns the manifest's path, which is
+    // the existing project's if it's skipped.
+    pub fn download(&self, id: &str) -> error::Result<String> {
+        let id = parse_valid_id(id)?;
+        if let Some(path) = self.existing_project(id) {
+            return Ok(path);
+        }
+        let shader = self.fetch(id)?;
+        Ok(self.save(&shader)?.path().to_string())
+    }
+
+    // The manifest of a project already saved for the shader, if it should be kept rather than
+    // downloading the shader again.
+    pub fn existing_project(&self, id: &str) -> Option<String> {
+        if self.on_existing != OnExisting::Skip {
+            return None;
+        }
+        let suffix = format!("_{}", id);
+        fs::read_dir(&self.output_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name == id || name.ends_with(&suffix)
+            })
+            .map(|entry| entry.path().join("project.toml"))
+            .find(|path| path.is_file())
+            .map(|path| {
+                info!(
+                    "Skipping shader {}, already saved to {}",
+                    id,
+                    path.display()
+                );
+                path.to_string_lossy().into_owned()
+            })
+    }
+
+    // Fetches a shader, given its id or URL, and adds its JSON to the cache.
+    pub fn fetch(&self, id: &str) -> error::Result<Shader> {
+        let id = parse_valid_id(id)?;
+        let json = self.get_shader_json(id)?;
+        let shader = Shader::from_json(&json)?;
+        if shader.info.published == Some(0) {
+            return Err(PrivateShaderError::new(id).into());
+        }
+        if !is_valid_id(&shader.info.id) {
+            return Err(InvalidShaderIdError::new(&shader.info.id).into());
+        }
+
+        if let Err(err) = ShaderCache::new().insert(&shader.info, &json) {
+            warn!("Failed to cache shader {}: {}", shader.info.id, err);
+        }
+        Ok(shader)
+    }
+
+    // Saves a fetched shader as a project in the output directory, unless one is already there
+    // and the overwrite policy says to keep it.
+    pub fn save(&self, shader: &Shader) -> error::Result<Saved> {
+        let downloaded = extract_from_json(shader);
+        let mut dir = self.output_dir.join(&downloaded.name);
+        if dir.exists() {
+            match self.on_existing {
+                OnExisting::Skip => {
+                    let path = dir.join("project.toml");
+                    info!(
+                        "Skipping shader {}, {} exists",
+                        shader.info.id,
+                        dir.display()
+                    );
+                    return Ok(Saved::Existing(path.to_string_lossy().into_owned()));
+                }
+                OnExisting::Overwrite => (),
+                OnExisting::Rename => {
+                    dir = (2..)
+                        .map(|n| self.output_dir.join(format!("{}_{}", downloaded.name, n)))
+                        .find(|dir| !dir.exists())
+                        .unwrap();
+                }
+            }
+        }
+
+        let path = save_project(downloaded, &dir, Some(self))?;
+        info!("Saved shader {} to {}", shader.info.id, path);
+        Ok(Saved::New(path))
+    }
+
+    // Ids of the shaders matching the query, which needs an API key.
+    pub fn query(&self, query: &Query) -> error::Result<Vec<String>> {
+        let api_key = match self.api_key {
+            Some(ref api_key) => api_key,
+            None => {
+                return Err(ShadertoyApiError::new("queries need an API key".to_string()).into());
+            }
+        };
+
+        // The terms are a path segment, so they need escaping.
+        let mut url = Url::parse(&format!("{}/api/v1/shaders", self.base_url))?;
+        if !query.terms.is_empty() {
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.push("query").push(&query.terms);
+            }
+        }
+        let mut params = vec![("key", api_key.clone()), ("num", query.limit.to_string())];
+        if let Some(ref sort) = query.sort {
+            params.push(("sort", sort.clone()));
+        }
+        params.extend(
+            query
+                .filters
+                .iter()
+                .map(|filter| ("filter", filter.clone())),
+        );
+
+        let response: QueryResponse = self.get_api(url, &params, "query results")?;
+        Ok(response.results)
+    }
+
+    // Fetches the shader's JSON through the official API if there's an API key, falling back to
+    // the endpoint used by the site itself (which needs no key, but isn't documented and can
+    // change).
+    fn get_shader_json(&self, id: &str) -> error::Result<Value> {
+        if let Some(ref api_key) = self.api_key {
+            let url = format!("{}/api/v1/shaders/{}", self.base_url, id);
+            let response = self.get_api(&url, &[("key", api_key.clone())], "shader response");
+            match response {
+                Ok(ShaderResponse { shader }) => return Ok(shader),
+                Err(err) => warn!("{}; falling back to the site's endpoint", err),
+            }
+        } else {
+            info!("No Shadertoy API key, using the site's endpoint");
+        }
+
+        self.get_site_json(id)
+    }
+
+    // Makes a request to the documented /api/v1 endpoints, which respond with {"Error": "..."} if
+    // the request fails, and parses the response as what it should be.
+    fn get_api<U, T>(&self, url: U, params: &[(&str, String)], what: &str) -> error::Result<T>
+    where
+        U: IntoUrl,
+        T: DeserializeOwned,
+    {
+        self.wait();
+        let mut res = self.client.get(url).query(params).send()?;
+        schema::parse_api(&response_json(&mut res)?, what)
+    }
+
+    // Fetches the shader's JSON from the endpoint used by the site itself, which responds with
+    // a list of shaders (empty if the shader is private or doesn't exist).
+    fn get_site_json(&self, id: &str) -> error::Result<Value> {
+        use reqwest::header::*;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            REFERER,
+            HeaderValue::from_str(&format!("{}/", self.base_url))?,
+        );
+        self.wait();
+        let mut res = self
+            .client
+            .post(&format!("{}/shadertoy/", self.base_url))
+            .headers(headers)
+            .form(&[("s", format!("{{\"shaders\": [\"{}\"]}}", id))])
+            .send()?;
+
+        let shaders: Vec<Value> = schema::parse(&response_json(&mut res)?, "list of shaders")?;
+        match shaders.into_iter().next() {
+            Some(shader) => Ok(shader),
+            None => Err(PrivateShaderError::new(id).into()),
+        }
+    }
+
+    // Fetches the media file at the url, relative to the base URL.
+    fn get_media(&self, url: &str) -> error::Result<Vec<u8>> {
+        let full_url = format!("{}{}", self.base_url, url);
+        info!("Downloading {}", full_url);
+        self.wait();
+        let mut res = self.client.get(&full_url).send()?;
+        if !res.status().is_success() {
+            return Err(HttpStatusError::new(&full_url, res.status().to_string()).into());
+        }
+        let mut contents = Vec::new();
+        res.copy_to(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+// Reads a response as JSON, failing if its status isn't a success or it isn't JSON.
+fn response_json(res: &mut Response) -> error::Result<Value> {
+    if !res.status().is_success() {
+        return Err(HttpStatusError::new(res.url().as_str(), res.status().to_string()).into());
+    }
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    serde_json::from_str(&body).map_err(|err| MalformedResponseError::new(err.to_string()).into())
+}
+
+// What to do when saving a project where there's already a file or directory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnExisting {
+    // Keep what's there, and don't download the shader again.
+    Skip,
+
+    // Write the project over what's there.
+    Overwrite,
+
+    // Save the project next to it, adding _2, _3, etc. to the name.
+    Rename,
+}
+
+// What saving a shader's project did, and the path of the project's manifest.
+pub enum Saved {
+    New(String),
+
+    // Nothing, since the project was already there.
+    Existing(String),
+}
+
+impl Saved {
+    pub fn path(&self) -> &str {
+        match *self {
+            Saved::New(ref path) | Saved::Existing(ref path) => path,
+        }
+    }
+}
+
+// A search for shaders through the API.
+pub struct Query {
+    // Words to search for. Empty to list every shader.
+    pub terms: String,
+
+    // How to sort the results: "name", "love", "popular", "newest" or "hot". None for the API's
+    // default (popular).
+    pub sort: Option<String>,
+
+    // Only find shaders with all these features, e.g. "multipass" or "soundoutput".
+    pub filters: Vec<String>,
+
+    // Maximum number of results.
+    pub limit: u32,
+}
+
+// Saves a shader from the cache as a project in the cache, without any network access. Returns
+// the manifest's path.
+pub fn load_cached(id: &str) -> error::Result<String> {
+    let id = parse_id(id);
+    let cache = ShaderCache::new();
+    let shader = Shader::from_json(&cache.get(id)?)?;
+    save_project(extract_from_json(&shader), &cache.project_dir(id)?, None)
+}
+
+// True if the id is what shadertoy.com uses, so it's safe to use in paths.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Strips the URL from a shader's id, failing if what's left isn't a valid id. Ids go into request
+// URLs and bodies, so they're checked before any request is made.
+fn parse_valid_id(id: &str) -> error::Result<&str> {
+    let id = parse_id(id);
+    if is_valid_id(id) {
+        Ok(id)
+    } else {
+        Err(InvalidShaderIdError::new(id).into())
+    }
+}
+
+// Turns the shader's name into something safe to use in a file name, e.g. "Seascape (v2)" into
+// "seascape_v2": only lowercase ASCII letters and digits, with anything else between them
+// replaced by a single underscore.
+fn sanitize_name(name: &str) -> String {
+    let mut sanitized = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            sanitized.push(c.to_ascii_lowercase());
+        } else if !sanitized.is_empty() && !sanitized.ends_with('_') {
+            sanitized.push('_');
+        }
+    }
+    sanitized.truncate(MAX_NAME_LEN);
+    sanitized.trim_end_matches('_').to_string()
 }
 
-fn get_shader_name_and_code(mut id: &str) -> error::Result<(String, String)> {
+// Strips the URL from "https://www.shadertoy.com/view/<id>", leaving the id. A query, fragment or
+// trailing slash after the id is dropped too.
+pub fn parse_id(id: &str) -> &str {
     let https_url = "https://www.shadertoy.com/view/";
     let http_url = "http://www.shadertoy.com/view/";
     let url = "www.shadertoy.com/view/";
 
     if id.starts_with(https_url) || id.starts_with(http_url) || id.starts_with(url) {
-        id = id.split_at(id.rfind("view/").unwrap() + 5).1;
+        let id = id.split_at(id.find("view/").unwrap() + 5).1;
+        id.split(['?', '#', '/']).next().unwrap()
+    } else {
+        id
     }
+}
 
-    let json = serde_json::from_str::<Value>(&get_json_string(id)?)?;
+// Writes the code of each pass, the media files and the manifest to dir. Media files are fetched
+// with the downloader unless they're cached, or only taken from the cache if there's none.
+fn save_project(
+    downloaded: DownloadedShader,
+    dir: &Path,
+    downloader: Option<&Downloader>,
+) -> error::Result<String> {
+    let DownloadedShader {
+        files,
+        mut manifest,
+        ..
+    } = downloaded;
 
-    extract_from_json(&json)
-}
+    fs::create_dir_all(dir).or_else(|err| return_save_shader_error(&dir.to_string_lossy(), err))?;
+    for (file_name, code) in files {
+        let path = dir.join(file_name);
+        fs::write(&path, code)
+            .or_else(|err| return_save_shader_error(&path.to_string_lossy(), err))?;
+    }
 
-fn get_json_string(id: &str) -> error::Result<String> {
-    let client = Client::new();
-    use reqwest::header::*;
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        REFERER,
-        HeaderValue::from_static("https://www.shadertoy.com/"),
-    );
-    let mut res = client
-        .post("https://www.shadertoy.com/shadertoy/")
-        .headers(headers)
-        .form(&[("s", format!("{{\"shaders\": [\"{}\"]}}", id))])
-        .send()?;
+    fetch_media(&mut manifest, dir, downloader);
 
-    let mut buf = String::new();
+    let project = Project {
+        manifest,
+        path: dir.join("project.toml"),
+    };
+    project.save()?;
+    Ok(project.path.to_string_lossy().into_owned())
+}
 
-    match res.read_to_string(&mut buf) {
-        Ok(_) => {
-            if buf == "[]" {
-                Err(InvalidShaderIdError::new(id).into())
-            } else {
-                Ok(buf)
+fn return_save_shader_error<E>(name: &str, err: io::Error) -> error::Result<E> {
+    Err(SaveShaderError::new(name, err).into())
+}
+
+// Copies the media files read by the manifest's channels (through the cache) into the project's
+// media/ directory and points the channels at them. Files that can't be fetched are skipped with a
+// warning, leaving their channels empty.
+fn fetch_media(manifest: &mut Manifest, dir: &Path, downloader: Option<&Downloader>) {
+    let cache = MediaCache::new();
+    for channel in manifest.channels_mut() {
+        let kind = channel.kind();
+        let (path, url) = match channel.file_mut() {
+            Some((path, &mut Some(ref url))) if path.is_none() && url.starts_with("/media/") => {
+                (path, url.clone())
             }
+            _ => continue,
+        };
+
+        let fetched: error::Result<Vec<String>> = media_urls(kind, &url)
+            .iter()
+            .map(|url| fetch_media_file(&cache, url, dir, downloader))
+            .collect();
+        match fetched {
+            Ok(paths) => *path = paths.into_iter().next(),
+            Err(err) => warn!("Skipping {} input: {}", kind, err),
         }
-        Err(err) => Err(err.into()),
     }
 }
 
-fn extract_from_json(json: &Value) -> error::Result<(String, String)> {
-    let name = format!(
-        "{}.frag",
-        json[0]["info"]["name"].as_str().unwrap().replace(' ', "_")
-    )
-    .to_lowercase();
-    let mut code = String::new();
+// URLs of the files of a channel's media. Cubemaps have five more faces, named after the first
+// one with suffixes _1 to _5.
+fn media_urls(kind: &str, url: &str) -> Vec<String> {
+    let mut urls = vec![url.to_string()];
+    if kind == "cubemap" {
+        if let Some(dot) = url.rfind('.') {
+            let (stem, extension) = url.split_at(dot);
+            urls.extend((1..6).map(|face| format!("{}_{}{}", stem, face, extension)));
+        }
+    }
+    urls
+}
+
+// Copies the file at the url into the project's media/ directory, fetching it unless it's already
+// cached (or failing if it isn't and there's no downloader). Returns its path relative to the
+// project.
+fn fetch_media_file(
+    cache: &MediaCache,
+    url: &str,
+    dir: &Path,
+    downloader: Option<&Downloader>,
+) -> error::Result<String> {
+    let cached = match (cache.get(url), downloader) {
+        (Some(cached), _) => cached,
+        (None, None) => {
+            return Err(DownloadMediaError::new(url, "not cached".to_string()).into());
+        }
+        (None, Some(downloader)) => cache.insert(url, &downloader.get_media(url)?)?,
+    };
+
+    // Media URLs are normally /media/a/<sha256>.<ext>, but names that aren't safe to use are
+    // replaced by the cached file's, which is named after its contents.
+    let file_name = url.rsplit('/').next().unwrap_or(url);
+    let safe = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.';
+    let file_name = if file_name.starts_with('.') || !file_name.chars().all(safe) {
+        cached
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        file_name.to_string()
+    };
+    let media_dir = dir.join("media");
+    let path = media_dir.join(&file_name);
+    fs::create_dir_all(&media_dir)
+        .and_then(|_| fs::copy(&cached, &path))
+        .or_else(|err| return_save_shader_error(&path.to_string_lossy(), err))?;
+    Ok(format!("media/{}", file_name))
+}
+
+// The kinds of renderpass a shader can have.
+#[derive(Clone, Copy, PartialEq)]
+enum PassKind {
+    Common,
+    Buffer(BufferId),
+    Cube,
+    Image,
+    Sound,
+}
+
+impl PassKind {
+    // Name of the file the pass's code is saved to.
+    fn file_name(self) -> String {
+        match self {
+            PassKind::Common => "common.glsl".to_string(),
+            PassKind::Buffer(id) => format!("{}.glsl", id.arg_name()),
+            PassKind::Cube => "cube_a.glsl".to_string(),
+            PassKind::Image => "image.glsl".to_string(),
+            PassKind::Sound => "sound.glsl".to_string(),
+        }
+    }
+}
+
+// A shader split into the code of each renderpass and a manifest describing them.
+struct DownloadedShader {
+    // Name of the project directory.
+    name: String,
+
+    // File name and code of each renderpass.
+    files: Vec<(String, String)>,
+
+    manifest: Manifest,
+}
+
+fn extract_from_json(shader: &Shader) -> DownloadedShader {
+    // Names can be anything, so they're sanitized, and the id keeps them apart.
+    let name = match sanitize_name(&shader.info.name) {
+        ref name if name.is_empty() => shader.info.id.clone(),
+        name => format!("{}_{}", name, shader.info.id),
+    };
+
+    // Buffers are normally named "Buffer A" through "Buffer D", but fall back to their order.
+    let mut kinds = Vec::new();
+    let mut passes: Vec<(PassKind, &RenderPass)> = Vec::new();
+    for renderpass in &shader.renderpasses {
+        let kind = match renderpass.kind.as_str() {
+            "common" => PassKind::Common,
+            "cubemap" => PassKind::Cube,
+            "sound" => PassKind::Sound,
+            "buffer" => {
+                let unused = |id: &BufferId| !kinds.contains(&PassKind::Buffer(*id));
+                let named = BufferId::ALL
+                    .iter()
+                    .cloned()
+                    .find(|id| renderpass.name == id.name() && unused(id));
+                match named.or_else(|| BufferId::ALL.iter().cloned().find(unused)) {
+                    Some(id) => PassKind::Buffer(id),
+                    None => {
+                        warn!("Skipping extra buffer {}", renderpass.name);
+                        continue;
+                    }
+                }
+            }
+            _ => PassKind::Image,
+        };
+        kinds.push(kind);
+        passes.push((kind, renderpass));
+    }
 
-    let shaders = json[0]["renderpass"].as_array().unwrap();
+    let mut files = Vec::new();
+    let mut manifest = Manifest {
+        title: Some(shader.info.name.clone()),
+        ..Manifest::default()
+    };
+    for &(kind, renderpass) in &passes {
+        files.push((kind.file_name(), renderpass.code.clone()));
 
-    if shaders.len() > 1 {
-        for shader in shaders {
-            if shader["name"] == "Image" {
-                code = String::from(shader["code"].as_str().unwrap());
+        if kind == PassKind::Common {
+            manifest.common = Some(kind.file_name());
+            continue;
+        }
+
+        let mut pass = PassManifest {
+            source: kind.file_name(),
+            ..PassManifest::default()
+        };
+        for input in &renderpass.inputs {
+            let channel = match channel_manifest(input, &passes) {
+                Some(channel) => channel,
+                None => continue,
+            };
+            match input.channel {
+                Some(0) => pass.channel0 = Some(channel),
+                Some(1) => pass.channel1 = Some(channel),
+                Some(2) => pass.channel2 = Some(channel),
+                Some(3) => pass.channel3 = Some(channel),
+                _ => warn!("Skipping input with invalid channel {:?}", input.channel),
             }
         }
-    } else {
-        code = String::from(shaders[0]["code"].as_str().unwrap());
+
+        match kind {
+            PassKind::Buffer(BufferId::A) => manifest.buffer_a = Some(pass),
+            PassKind::Buffer(BufferId::B) => manifest.buffer_b = Some(pass),
+            PassKind::Buffer(BufferId::C) => manifest.buffer_c = Some(pass),
+            PassKind::Buffer(BufferId::D) => manifest.buffer_d = Some(pass),
+            PassKind::Cube => manifest.cube_a = Some(pass),
+            PassKind::Image => manifest.image = pass,
+            PassKind::Sound => manifest.sound = Some(pass),
+            PassKind::Common => (),
+        }
     }
 
-    Ok((name, code))
+    DownloadedShader {
+        name,
+        files,
+        manifest,
+    }
+}
+
+// Converts one of a renderpass's inputs to a channel of the manifest, or None (with a warning) if
+// the input isn't supported.
+fn channel_manifest(
+    input: &Input,
+    renderpasses: &[(PassKind, &RenderPass)],
+) -> Option<ChannelManifest> {
+    let sampler = &input.sampler;
+    let url = input.src.clone();
+
+    match input.ctype.as_deref() {
+        Some("texture") => Some(ChannelManifest::Texture {
+            path: None,
+            url,
+            filter: sampler.filter.unwrap_or(Filter::Mipmap),
+            wrap: sampler.wrap.unwrap_or(Wrap::Repeat),
+            vflip: sampler.vflip.unwrap_or(true),
+            srgb: sampler.srgb.unwrap_or(false),
+        }),
+        Some("buffer") => {
+            // Buffers are read through the id of the pass's output. Older shaders only have the
+            // placeholder image for the buffer, e.g. /media/previz/buffer01.png for Buffer B.
+            let by_output = renderpasses.iter().find(|&&(_, renderpass)| {
+                renderpass
+                    .outputs
+                    .iter()
+                    .any(|output| output.id.is_some() && output.id == input.id)
+            });
+            let buffer = match by_output {
+                Some(&(PassKind::Buffer(id), _)) => Some(id),
+                Some(_) => None,
+                None => BufferId::ALL.iter().cloned().find(|id| {
+                    url.as_deref() == Some(&format!("/media/previz/buffer0{}.png", id.index()))
+                }),
+            };
+            match buffer {
+                Some(id) => Some(ChannelManifest::Buffer {
+                    buffer: id.arg_name().to_string(),
+                    filter: sampler.filter.unwrap_or(Filter::Linear),
+                    wrap: sampler.wrap.unwrap_or(Wrap::Clamp),
+                }),
+                None => {
+                    let id = input.id.as_ref().map(|id| id.to_string());
+                    warn!(
+                        "Skipping input reading unknown buffer {}",
+                        id.unwrap_or_default()
+                    );
+                    None
+                }
+            }
+        }
+        // Cubemaps rendered by the Cube A pass only have a placeholder image, and are read like
+        // buffers.
+        Some("cubemap")
+            if url
+                .as_ref()
+                .is_some_and(|url| url.starts_with("/media/previz/")) =>
+        {
+            Some(ChannelManifest::Buffer {
+                buffer: "cube_a".to_string(),
+                filter: sampler.filter.unwrap_or(Filter::Linear),
+                wrap: sampler.wrap.unwrap_or(Wrap::Clamp),
+            })
+        }
+        Some("cubemap") => Some(ChannelManifest::Cubemap {
+            path: None,
+            url,
+            faces: Vec::new(),
+            filter: sampler.filter.unwrap_or(Filter::Mipmap),
+            wrap: sampler.wrap.unwrap_or(Wrap::Clamp),
+            vflip: sampler.vflip.unwrap_or(false),
+            srgb: sampler.srgb.unwrap_or(false),
+        }),
+        Some("volume") => Some(ChannelManifest::Volume {
+            path: None,
+            url,
+            slices: Vec::new(),
+            size: None,
+            channels: None,
+            depth: None,
+            filter: sampler.filter.unwrap_or(Filter::Mipmap),
+            wrap: sampler.wrap.unwrap_or(Wrap::Repeat),
+        }),
+        Some("keyboard") => Some(ChannelManifest::Keyboard),
+        Some("music") | Some("musicstream") | Some("mic") => {
+            Some(ChannelManifest::Audio { path: None, url })
+        }
+        ctype => {
+            warn!("Skipping unsupported {} input", ctype.unwrap_or("unknown"));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_names() {
+        assert_eq!(sanitize_name("Seascape (v2)"), "seascape_v2");
+        assert_eq!(sanitize_name("a/b\\c"), "a_b_c");
+        assert_eq!(sanitize_name("../../etc/passwd"), "etc_passwd");
+        assert_eq!(sanitize_name(".hidden"), "hidden");
+        assert_eq!(sanitize_name("..."), "");
+        assert_eq!(sanitize_name(""), "");
+        assert_eq!(sanitize_name("Café Über"), "caf_ber");
+        assert_eq!(sanitize_name("日本語"), "");
+        assert_eq!(sanitize_name(&"x".repeat(100)).len(), MAX_NAME_LEN);
+        assert_eq!(
+            sanitize_name(&format!("{} y", "x".repeat(MAX_NAME_LEN))),
+            "x".repeat(MAX_NAME_LEN)
+        );
+    }
+
+    #[test]
+    fn parses_ids() {
+        assert_eq!(parse_id("XdXGzn"), "XdXGzn");
+        assert_eq!(parse_id("https://www.shadertoy.com/view/XdXGzn"), "XdXGzn");
+        assert_eq!(parse_id("http://www.shadertoy.com/view/XdXGzn"), "XdXGzn");
+        assert_eq!(parse_id("www.shadertoy.com/view/XdXGzn"), "XdXGzn");
+        assert_eq!(
+            parse_id("https://www.shadertoy.com/view/XdXGzn?foo"),
+            "XdXGzn"
+        );
+        assert_eq!(
+            parse_id("https://www.shadertoy.com/view/XdXGzn#comments"),
+            "XdXGzn"
+        );
+        assert_eq!(parse_id("https://www.shadertoy.com/view/XdXGzn/"), "XdXGzn");
+        assert_eq!(parse_id("https://www.shadertoy.com/view/"), "");
+        assert!(!is_valid_id(parse_id("https://www.shadertoy.com/view/")));
+        assert!(!is_valid_id(parse_id("../XdXGzn")));
+    }
+
+    fn shader(json: &str) -> Shader {
+        Shader::from_json(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn extracts_projects() {
+        let shader = shader(
+            r#"{
+                "info": {"id": "XdXGzn", "name": "Fluid (v2)"},
+                "renderpass": [
+                    {"type": "common", "name": "Common", "code": "// common"},
+                    {
+                        "type": "buffer", "name": "Buffer A", "code": "// a",
+                        "outputs": [{"id": "4dXGR8"}],
+                        "inputs": [
+                            {"id": "4dXGR8", "ctype": "buffer", "channel": 0,
+                             "sampler": {"filter": "nearest", "wrap": "clamp"}},
+                            {"id": 30, "src": "/media/a/noise.png", "ctype": "texture",
+                             "channel": 1, "sampler": {"filter": "mipmap", "wrap": "repeat",
+                                                       "vflip": "true", "srgb": "false"}}
+                        ]
+                    },
+                    {
+                        "type": "buffer", "name": "Feedback", "code": "// b",
+                        "outputs": [{"id": "XsXGR8"}],
+                        "inputs": [
+                            {"id": "zzzzzz", "filepath": "/media/previz/buffer00.png",
+                             "type": "buffer", "channel": 0},
+                            {"id": 33, "ctype": "keyboard", "channel": 1},
+                            {"id": 40, "ctype": "webcam", "channel": 2},
+                            {"id": 30, "src": "/media/a/noise.png", "ctype": "texture",
+                             "channel": 7}
+                        ]
+                    },
+                    {
+                        "type": "cubemap", "name": "Cube A", "code": "// cube",
+                        "outputs": [{"id": "4sXGR8"}],
+                        "inputs": [
+                            {"id": "4sXGR8", "src": "/media/previz/cubemap00.png",
+                             "ctype": "cubemap", "channel": 0}
+                        ]
+                    },
+                    {
+                        "type": "image", "name": "Image", "code": "// image",
+                        "inputs": [
+                            {"id": "XsXGR8", "ctype": "buffer", "channel": 0},
+                            {"id": 22, "src": "/media/a/sky.jpg", "ctype": "cubemap",
+                             "channel": 1},
+                            {"id": 39, "src": "/media/a/noise.bin", "ctype": "volume",
+                             "channel": 2},
+                            {"id": 18, "src": "/media/a/song.mp3", "ctype": "music",
+                             "channel": 3}
+                        ]
+                    },
+                    {"type": "sound", "name": "Sound", "code": "// sound"}
+                ]
+            }"#,
+        );
+        let downloaded = extract_from_json(&shader);
+        assert_eq!(downloaded.name, "fluid_v2_XdXGzn");
+
+        let files: Vec<(&str, &str)> = downloaded
+            .files
+            .iter()
+            .map(|(name, code)| (name.as_str(), code.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("common.glsl", "// common"),
+                ("buffer_a.glsl", "// a"),
+                ("buffer_b.glsl", "// b"),
+                ("cube_a.glsl", "// cube"),
+                ("image.glsl", "// image"),
+                ("sound.glsl", "// sound"),
+            ]
+        );
+
+        let expected: Value = serde_json::from_str(
+            r#"{
+                "title": "Fluid (v2)",
+                "common": "common.glsl",
+                "image": {
+                    "source": "image.glsl",
+                    "channel0": {"type": "buffer", "buffer": "buffer_b", "filter": "linear",
+                                 "wrap": "clamp"},
+                    "channel1": {"type": "cubemap", "url": "/media/a/sky.jpg", "filter": "mipmap",
+                                 "wrap": "clamp", "vflip": false, "srgb": false},
+                    "channel2": {"type": "volume", "url": "/media/a/noise.bin", "filter": "mipmap",
+                                 "wrap": "repeat"},
+                    "channel3": {"type": "audio", "url": "/media/a/song.mp3"}
+                },
+                "buffer_a": {
+                    "source": "buffer_a.glsl",
+                    "channel0": {"type": "buffer", "buffer": "buffer_a", "filter": "nearest",
+                                 "wrap": "clamp"},
+                    "channel1": {"type": "texture", "url": "/media/a/noise.png",
+                                 "filter": "mipmap", "wrap": "repeat", "vflip": true,
+                                 "srgb": false}
+                },
+                "buffer_b": {
+                    "source": "buffer_b.glsl",
+                    "channel0": {"type": "buffer", "buffer": "buffer_a", "filter": "linear",
+                                 "wrap": "clamp"},
+                    "channel1": {"type": "keyboard"}
+                },
+                "cube_a": {
+                    "source": "cube_a.glsl",
+                    "channel0": {"type": "buffer", "buffer": "cube_a", "filter": "linear",
+                                 "wrap": "clamp"}
+                },
+                "sound": {"source": "sound.glsl"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&downloaded.manifest).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn names_projects_and_buffers() {
+        // Buffers with clashing or unknown names take the first free letter, and a fifth is
+        // dropped.
+        let shader = shader(
+            r#"{
+                "info": {"id": "XdXGzn", "name": "日本語"},
+                "renderpass": [
+                    {"type": "buffer", "name": "Buffer C"},
+                    {"type": "buffer", "name": "Buffer C"},
+                    {"type": "buffer", "name": "Buffer A"},
+                    {"type": "buffer", "name": "Buffer B"},
+                    {"type": "buffer", "name": "Buffer E"},
+                    {"type": "image", "name": "Image"}
+                ]
+            }"#,
+        );
+        let downloaded = extract_from_json(&shader);
+        assert_eq!(downloaded.name, "XdXGzn");
+        let files: Vec<&str> = downloaded
+            .files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "buffer_c.glsl",
+                "buffer_a.glsl",
+                "buffer_b.glsl",
+                "buffer_d.glsl",
+                "image.glsl"
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_cubemap_faces() {
+        assert_eq!(
+            media_urls("cubemap", "/media/a/sky.jpg"),
+            [
+                "/media/a/sky.jpg",
+                "/media/a/sky_1.jpg",
+                "/media/a/sky_2.jpg",
+                "/media/a/sky_3.jpg",
+                "/media/a/sky_4.jpg",
+                "/media/a/sky_5.jpg",
+            ]
+        );
+        assert_eq!(
+            media_urls("texture", "/media/a/noise.png"),
+            ["/media/a/noise.png"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ids_before_requests() {
+        // Nothing listens on the discard port, so a request would fail differently.
+        let downloader = Downloader::new(
+            "http://127.0.0.1:9",
+            Some("key".to_string()),
+            0.0,
+            PathBuf::new(),
+            OnExisting::Skip,
+        );
+        let ids = [
+            "ab\"c",
+            "../XdXGzn",
+            "XdXGzn/../../users",
+            "",
+            "https://www.shadertoy.com/view/",
+        ];
+        for id in ids.iter() {
+            match downloader.fetch(id) {
+                Err(err) => assert!(err.to_string().starts_with("Invalid shader ID"), "{}", err),
+                Ok(_) => panic!("fetched {}", id),
+            }
+        }
+        assert_eq!(
+            parse_valid_id("https://www.shadertoy.com/view/XdXGzn").unwrap(),
+            "XdXGzn"
+        );
+    }
 }
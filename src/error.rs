@@ -6,15 +6,6 @@ use std::io;
 
 pub type Result<T> = anyhow::Result<T>;
 
-// All unsupported uniforms. Attempting to use any of these in a shader will result in an error.
-pub static UNSUPPORTED_UNIFORMS: [&str; 5] = [
-    "iTimeDelta",
-    "iChannelTime",
-    "iChannelResolution",
-    "iDate",
-    "iSampleRate",
-];
-
 // Custom error for failing to load shaders.
 #[derive(Debug)]
 pub struct LoadShaderError {
@@ -97,25 +88,558 @@ impl fmt::Display for SaveShaderError {
     }
 }
 
-// Custom error for attempting to run a shader with unsupported uniforms.
+// Custom error for malformed --channel bindings.
+#[derive(Debug)]
+pub struct InvalidChannelBindingError {
+    binding: String,
+}
+impl InvalidChannelBindingError {
+    pub fn new(binding: &str) -> InvalidChannelBindingError {
+        InvalidChannelBindingError {
+            binding: binding.to_string(),
+        }
+    }
+}
+impl Error for InvalidChannelBindingError {}
+impl fmt::Display for InvalidChannelBindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid channel binding {} (expected <pass>:<channel>=<input>, e.g. image:0=buffer_a)",
+            self.binding
+        )
+    }
+}
+
+// Custom error for binding a channel to a buffer pass that has no shader.
+#[derive(Debug)]
+pub struct MissingBufferError {
+    buffer: String,
+}
+impl MissingBufferError {
+    pub fn new(buffer: &str) -> MissingBufferError {
+        MissingBufferError {
+            buffer: buffer.to_string(),
+        }
+    }
+}
+impl Error for MissingBufferError {}
+impl fmt::Display for MissingBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "A channel reads from {}, but no shader was given for it",
+            self.buffer
+        )
+    }
+}
+
+// Custom error for failing to build the pipeline for a render pass.
+#[derive(Debug)]
+pub struct CreatePipelineError {
+    pass: String,
+    error: String,
+}
+impl CreatePipelineError {
+    pub fn new(pass: &str, error: String) -> CreatePipelineError {
+        CreatePipelineError {
+            pass: pass.to_string(),
+            error,
+        }
+    }
+}
+impl Error for CreatePipelineError {}
+impl fmt::Display for CreatePipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Failed to create pipeline for {}:\n{}",
+            self.pass, self.error
+        )
+    }
+}
+
+// Custom error for failing to create an offscreen OpenGL context.
+#[derive(Debug)]
+pub struct HeadlessContextError {
+    error: String,
+}
+impl HeadlessContextError {
+    pub fn new(error: String) -> HeadlessContextError {
+        HeadlessContextError { error }
+    }
+}
+impl Error for HeadlessContextError {}
+impl fmt::Display for HeadlessContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Failed to create offscreen OpenGL context: {}",
+            self.error
+        )
+    }
+}
+
+// Custom error for failing to save rendered sound.
+#[derive(Debug)]
+pub struct SaveSoundError {
+    path: String,
+    error: String,
+}
+impl SaveSoundError {
+    pub fn new(path: &str, error: String) -> SaveSoundError {
+        SaveSoundError {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+impl Error for SaveSoundError {}
+impl fmt::Display for SaveSoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error saving sound {}: {}", self.path, self.error)
+    }
+}
+
+// Custom error for failing to read back rendered pixels.
+#[derive(Debug)]
+pub struct ReadPixelsError {
+    error: String,
+}
+impl ReadPixelsError {
+    pub fn new(error: String) -> ReadPixelsError {
+        ReadPixelsError { error }
+    }
+}
+impl Error for ReadPixelsError {}
+impl fmt::Display for ReadPixelsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to read back rendered pixels: {}", self.error)
+    }
+}
+
+// Custom error for rendering to an image file of unknown format.
+#[derive(Debug)]
+pub struct UnsupportedImageFormatError {
+    path: String,
+}
+impl UnsupportedImageFormatError {
+    pub fn new(path: &str) -> UnsupportedImageFormatError {
+        UnsupportedImageFormatError {
+            path: path.to_string(),
+        }
+    }
+}
+impl Error for UnsupportedImageFormatError {}
+impl fmt::Display for UnsupportedImageFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unsupported image format for {} (expected .png or .exr)",
+            self.path
+        )
+    }
+}
+
+// Custom error for failing to save a rendered image.
+#[derive(Debug)]
+pub struct SaveImageError {
+    path: String,
+    error: String,
+}
+impl SaveImageError {
+    pub fn new(path: &str, error: String) -> SaveImageError {
+        SaveImageError {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+impl Error for SaveImageError {}
+impl fmt::Display for SaveImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error saving image {}: {}", self.path, self.error)
+    }
+}
+
+// Custom error for specifying an invalid iMouse value.
+#[derive(Debug)]
+pub struct InvalidMouseError {
+    mouse: String,
+}
+impl InvalidMouseError {
+    pub fn new(mouse: &str) -> InvalidMouseError {
+        InvalidMouseError {
+            mouse: mouse.to_string(),
+        }
+    }
+}
+impl Error for InvalidMouseError {}
+impl fmt::Display for InvalidMouseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid mouse {} (expected x,y,z,w, e.g. 0,0,0,0)",
+            self.mouse
+        )
+    }
+}
+
+// Custom error for failing to write a recorded video stream.
+#[derive(Debug)]
+pub struct SaveVideoError {
+    path: String,
+    error: io::Error,
+}
+impl SaveVideoError {
+    pub fn new(path: &str, error: io::Error) -> SaveVideoError {
+        SaveVideoError {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+impl Error for SaveVideoError {}
+impl fmt::Display for SaveVideoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error saving video {}: {}", self.path, self.error)
+    }
+}
+
+// Custom error for specifying zero for a count that must be positive, e.g. --fps 0.
+#[derive(Debug)]
+pub struct ZeroCountError {
+    option: String,
+}
+impl ZeroCountError {
+    pub fn new(option: &str) -> ZeroCountError {
+        ZeroCountError {
+            option: option.to_string(),
+        }
+    }
+}
+impl Error for ZeroCountError {}
+impl fmt::Display for ZeroCountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid --{} 0 (expected at least 1)", self.option)
+    }
+}
+
+// Custom error for specifying an invalid iDate value.
+#[derive(Debug)]
+pub struct InvalidDateError {
+    date: String,
+}
+impl InvalidDateError {
+    pub fn new(date: &str) -> InvalidDateError {
+        InvalidDateError {
+            date: date.to_string(),
+        }
+    }
+}
+impl Error for InvalidDateError {}
+impl fmt::Display for InvalidDateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid date {} (expected year,month,day,seconds, e.g. 2017,0,1,0)",
+            self.date
+        )
+    }
+}
+
+// Custom error for raw texture data that doesn't match its dimensions.
+#[derive(Debug)]
+pub struct InvalidTextureError {
+    width: u32,
+    height: u32,
+    len: usize,
+}
+impl InvalidTextureError {
+    pub fn new(width: u32, height: u32, len: usize) -> InvalidTextureError {
+        InvalidTextureError { width, height, len }
+    }
+}
+impl Error for InvalidTextureError {}
+impl fmt::Display for InvalidTextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid {}x{} RGBA texture: expected {} bytes, got {}",
+            self.width,
+            self.height,
+            self.width as usize * self.height as usize * 4,
+            self.len
+        )
+    }
+}
+
+// Custom error for cubemaps whose faces can't be made out.
+#[derive(Debug)]
+pub struct InvalidCubemapError {
+    path: String,
+    reason: String,
+}
+impl InvalidCubemapError {
+    pub fn new(path: &str, reason: String) -> InvalidCubemapError {
+        InvalidCubemapError {
+            path: path.to_string(),
+            reason,
+        }
+    }
+}
+impl Error for InvalidCubemapError {}
+impl fmt::Display for InvalidCubemapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid cubemap {}: {}", self.path, self.reason)
+    }
+}
+
+// Custom error for volumes whose texels can't be made out.
+#[derive(Debug)]
+pub struct InvalidVolumeError {
+    path: String,
+    reason: String,
+}
+impl InvalidVolumeError {
+    pub fn new(path: &str, reason: String) -> InvalidVolumeError {
+        InvalidVolumeError {
+            path: path.to_string(),
+            reason,
+        }
+    }
+}
+impl Error for InvalidVolumeError {}
+impl fmt::Display for InvalidVolumeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid volume {}: {}", self.path, self.reason)
+    }
+}
+
+// Custom error for a channel reading a texture that doesn't exist.
+#[derive(Debug)]
+pub struct MissingTextureError {
+    index: usize,
+}
+impl MissingTextureError {
+    pub fn new(index: usize) -> MissingTextureError {
+        MissingTextureError { index }
+    }
+}
+impl Error for MissingTextureError {}
+impl fmt::Display for MissingTextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "A channel reads texture {}, which doesn't exist",
+            self.index
+        )
+    }
+}
+
+// Custom error for a project manifest that can't be parsed.
+#[derive(Debug)]
+pub struct InvalidManifestError {
+    path: String,
+    error: String,
+}
+impl InvalidManifestError {
+    pub fn new(path: &str, error: String) -> InvalidManifestError {
+        InvalidManifestError {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+impl Error for InvalidManifestError {}
+impl fmt::Display for InvalidManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid project manifest {}: {}", self.path, self.error)
+    }
+}
+
+// Custom error for failing to download a texture or other media file.
+#[derive(Debug)]
+pub struct DownloadMediaError {
+    url: String,
+    error: String,
+}
+impl DownloadMediaError {
+    pub fn new(url: &str, error: String) -> DownloadMediaError {
+        DownloadMediaError {
+            url: url.to_string(),
+            error,
+        }
+    }
+}
+impl Error for DownloadMediaError {}
+impl fmt::Display for DownloadMediaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to download {}: {}", self.url, self.error)
+    }
+}
+
+// Custom error for running a shader that hasn't been downloaded.
+#[derive(Debug)]
+pub struct NotCachedError {
+    id: String,
+}
+impl NotCachedError {
+    pub fn new(id: &str) -> NotCachedError {
+        NotCachedError { id: id.to_string() }
+    }
+}
+impl Error for NotCachedError {}
+impl fmt::Display for NotCachedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Shader {} isn't cached; download it first with `shadertoy get {}`",
+            self.id, self.id
+        )
+    }
+}
+
+// Custom error for requests to the Shadertoy API that fail.
+#[derive(Debug)]
+pub struct ShadertoyApiError {
+    error: String,
+}
+impl ShadertoyApiError {
+    pub fn new(error: String) -> ShadertoyApiError {
+        ShadertoyApiError { error }
+    }
+}
+impl Error for ShadertoyApiError {}
+impl fmt::Display for ShadertoyApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Shadertoy API request failed: {}", self.error)
+    }
+}
+
+// Custom error for a config file that can't be parsed.
+#[derive(Debug)]
+pub struct InvalidConfigError {
+    path: String,
+    error: String,
+}
+impl InvalidConfigError {
+    pub fn new(path: &str, error: String) -> InvalidConfigError {
+        InvalidConfigError {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+impl Error for InvalidConfigError {}
+impl fmt::Display for InvalidConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid config file {}: {}", self.path, self.error)
+    }
+}
+
+// Custom error for a file of shader ids that can't be read.
+#[derive(Debug)]
+pub struct ReadIdListError {
+    path: String,
+    error: io::Error,
+}
+impl ReadIdListError {
+    pub fn new(path: &str, error: io::Error) -> ReadIdListError {
+        ReadIdListError {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+impl Error for ReadIdListError {}
+impl fmt::Display for ReadIdListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error reading shader ids from {}: {}",
+            self.path, self.error
+        )
+    }
+}
+
+// Custom error for batch downloads where some shaders failed.
+#[derive(Debug)]
+pub struct BatchDownloadError {
+    failed: usize,
+    total: usize,
+}
+impl BatchDownloadError {
+    pub fn new(failed: usize, total: usize) -> BatchDownloadError {
+        BatchDownloadError { failed, total }
+    }
+}
+impl Error for BatchDownloadError {}
+impl fmt::Display for BatchDownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Failed to download {} of {} shaders",
+            self.failed, self.total
+        )
+    }
+}
+
+// Custom error for shaders that are private (or don't exist).
+#[derive(Debug)]
+pub struct PrivateShaderError {
+    id: String,
+}
+impl PrivateShaderError {
+    pub fn new(id: &str) -> PrivateShaderError {
+        PrivateShaderError { id: id.to_string() }
+    }
+}
+impl Error for PrivateShaderError {}
+impl fmt::Display for PrivateShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Shader {} is private or doesn't exist", self.id)
+    }
+}
+
+// Custom error for responses from shadertoy.com that can't be understood.
+#[derive(Debug)]
+pub struct MalformedResponseError {
+    error: String,
+}
+impl MalformedResponseError {
+    pub fn new(error: String) -> MalformedResponseError {
+        MalformedResponseError { error }
+    }
+}
+impl Error for MalformedResponseError {}
+impl fmt::Display for MalformedResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Malformed response from Shadertoy: {}", self.error)
+    }
+}
+
+// Custom error for requests answered with an HTTP error status.
 #[derive(Debug)]
-pub struct UnsupportedUniformError {
-    unsupported_uniforms: Vec<String>,
+pub struct HttpStatusError {
+    url: String,
+    status: String,
 }
-impl UnsupportedUniformError {
-    pub fn new(unsupported_uniforms: Vec<String>) -> UnsupportedUniformError {
-        UnsupportedUniformError {
-            unsupported_uniforms,
+impl HttpStatusError {
+    pub fn new(url: &str, status: String) -> HttpStatusError {
+        HttpStatusError {
+            url: url.to_string(),
+            status,
         }
     }
 }
-impl Error for UnsupportedUniformError {}
-impl fmt::Display for UnsupportedUniformError {
+impl Error for HttpStatusError {}
+impl fmt::Display for HttpStatusError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "The following uniforms are not supported: {:?}",
-            self.unsupported_uniforms
+            "Request to {} failed with status {}",
+            self.url, self.status
         )
     }
 }
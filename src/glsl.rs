@@ -0,0 +1,345 @@
+// Translation of the GLSL ES 3.00 that Shadertoy shaders are written in to the desktop GLSL 1.50
+// the renderer compiles. Shaders are rewritten token by token rather than parsed, so only
+// constructs that can be recognized locally are handled:
+//
+// - #version and ES extension directives are dropped, since the prefix sets the version.
+// - Precision statements and qualifiers, meaningless on desktop, are dropped.
+// - ES texture functions (texture2D, textureCube, texture2DLodEXT, ...) become their GLSL 1.50
+//   equivalents.
+// - mod() calls go through overloads that also take ints and mixed int/float arguments, which
+//   WebGL implementations are often lenient about.
+// - Unsized array constructors (float[](...)) get their size, which some drivers require.
+//
+// Rewrites never add or remove lines, so compiler errors still point at the right line.
+
+// Defines and helper functions the translated code relies on, to be placed before it.
+pub const COMPAT_PREFIX: &str = "
+    #define HW_PERFORMANCE 1
+
+    float _compat_mod(float x, float y) { return mod(x, y); }
+    vec2  _compat_mod(vec2 x, vec2 y)   { return mod(x, y); }
+    vec3  _compat_mod(vec3 x, vec3 y)   { return mod(x, y); }
+    vec4  _compat_mod(vec4 x, vec4 y)   { return mod(x, y); }
+    vec2  _compat_mod(vec2 x, float y)  { return mod(x, y); }
+    vec3  _compat_mod(vec3 x, float y)  { return mod(x, y); }
+    vec4  _compat_mod(vec4 x, float y)  { return mod(x, y); }
+    int   _compat_mod(int x, int y)     { return x - y * int(floor(float(x) / float(y))); }
+    ivec2 _compat_mod(ivec2 x, ivec2 y) { return x - y * ivec2(floor(vec2(x) / vec2(y))); }
+    ivec3 _compat_mod(ivec3 x, ivec3 y) { return x - y * ivec3(floor(vec3(x) / vec3(y))); }
+    ivec4 _compat_mod(ivec4 x, ivec4 y) { return x - y * ivec4(floor(vec4(x) / vec4(y))); }
+    ivec2 _compat_mod(ivec2 x, int y)   { return _compat_mod(x, ivec2(y)); }
+    ivec3 _compat_mod(ivec3 x, int y)   { return _compat_mod(x, ivec3(y)); }
+    ivec4 _compat_mod(ivec4 x, int y)   { return _compat_mod(x, ivec4(y)); }
+";
+
+// ES functions and what they're called in GLSL 1.50.
+const RENAMED_FUNCTIONS: [(&str, &str); 10] = [
+    ("texture2D", "texture"),
+    ("texture2DLod", "textureLod"),
+    ("texture2DLodEXT", "textureLod"),
+    ("texture2DProj", "textureProj"),
+    ("texture2DProjLod", "textureProjLod"),
+    ("texture2DGradEXT", "textureGrad"),
+    ("textureCube", "texture"),
+    ("textureCubeLod", "textureLod"),
+    ("textureCubeLodEXT", "textureLod"),
+    ("mod", "_compat_mod"),
+];
+
+const PRECISION_QUALIFIERS: [&str; 3] = ["lowp", "mediump", "highp"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Ident,
+    Number,
+    Punct(char),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+
+    // True if it's the first token on its line.
+    line_start: bool,
+}
+
+// Splits the source into identifiers, numbers and punctuation, skipping whitespace and comments.
+fn tokenize(src: &str) -> Vec<Token> {
+    let bytes = src.as_bytes();
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut tokens = Vec::new();
+    let mut line_start = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let kind = match bytes[i] {
+            b'\n' => {
+                line_start = true;
+                i += 1;
+                continue;
+            }
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = src[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = src[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                line_start |= src[i..end].contains('\n');
+                i = end;
+                continue;
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while i < bytes.len() && is_ident(bytes[i]) {
+                    i += 1;
+                }
+                Kind::Ident
+            }
+            b if b.is_ascii_digit()
+                || (b == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) =>
+            {
+                while i < bytes.len() && (is_ident(bytes[i]) || bytes[i] == b'.') {
+                    i += 1;
+                }
+                Kind::Number
+            }
+            _ => {
+                let c = src[i..].chars().next().unwrap();
+                i += c.len_utf8();
+                Kind::Punct(c)
+            }
+        };
+        tokens.push(Token {
+            kind,
+            start,
+            end: i,
+            line_start,
+        });
+        line_start = false;
+    }
+    tokens
+}
+
+// Translates a shader (or the common code shared by its passes) to desktop GLSL.
+pub fn translate(src: &str) -> String {
+    let tokens = tokenize(src);
+    let text = |token: &Token| &src[token.start..token.end];
+    let is = |i: usize, kind: Kind| tokens.get(i).is_some_and(|token| token.kind == kind);
+
+    // Names the shader defines as macros are left alone, so its own compatibility macros (e.g.
+    // "#define texture2D texture") keep working.
+    let defined: Vec<&str> = tokens
+        .windows(3)
+        .filter(|w| w[0].kind == Kind::Punct('#') && w[0].line_start && text(&w[1]) == "define")
+        .map(|w| text(&w[2]))
+        .collect();
+
+    // Replacements of byte ranges of the source, in order.
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        match token.kind {
+            // #version and ES extensions, up to the end of the line.
+            Kind::Punct('#') if token.line_start => {
+                let directive = tokens.get(i + 1).map(&text);
+                let extension = tokens.get(i + 2).map_or("", &text);
+                if directive == Some("version")
+                    || (directive == Some("extension")
+                        && (extension.starts_with("GL_OES_") || extension.starts_with("GL_EXT_")))
+                {
+                    let end = src[token.start..]
+                        .find('\n')
+                        .map_or(src.len(), |end| token.start + end);
+                    edits.push((token.start, end, String::new()));
+                    while i < tokens.len() && tokens[i].start < end {
+                        i += 1;
+                    }
+                    continue;
+                }
+            }
+            Kind::Ident if defined.contains(&text(&token)) => (),
+
+            // "precision highp float;"
+            Kind::Ident if text(&token) == "precision" => {
+                let end = tokens[i..]
+                    .iter()
+                    .position(|token| token.kind == Kind::Punct(';'))
+                    .map_or(tokens.len() - 1, |n| i + n);
+                edits.push((
+                    token.start,
+                    tokens[end].end,
+                    blank(&src[token.start..tokens[end].end]),
+                ));
+                i = end + 1;
+                continue;
+            }
+            Kind::Ident if PRECISION_QUALIFIERS.contains(&text(&token)) => {
+                edits.push((token.start, token.end, String::new()));
+            }
+
+            // Function calls, but not fields of structs with the same name.
+            Kind::Ident
+                if is(i + 1, Kind::Punct('(')) && !(i > 0 && is(i - 1, Kind::Punct('.'))) =>
+            {
+                if let Some(&(_, renamed)) = RENAMED_FUNCTIONS
+                    .iter()
+                    .find(|&&(name, _)| name == text(&token))
+                {
+                    edits.push((token.start, token.end, renamed.to_string()));
+                }
+            }
+
+            // "float[](1.0, 2.0)" becomes "float[2](1.0, 2.0)".
+            Kind::Ident
+                if is(i + 1, Kind::Punct('['))
+                    && is(i + 2, Kind::Punct(']'))
+                    && is(i + 3, Kind::Punct('(')) =>
+            {
+                if let Some(len) = count_args(&tokens[i + 3..]) {
+                    edits.push((tokens[i + 2].start, tokens[i + 2].start, len.to_string()));
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+
+    let mut translated = String::with_capacity(src.len());
+    let mut last = 0;
+    for (start, end, replacement) in edits {
+        translated.push_str(&src[last..start]);
+        translated.push_str(&replacement);
+        last = end;
+    }
+    translated.push_str(&src[last..]);
+    translated
+}
+
+// Number of arguments in the parenthesized list starting at the first token, or None if it isn't
+// closed.
+fn count_args(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    let mut args = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            Kind::Punct('(') | Kind::Punct('[') | Kind::Punct('{') => depth += 1,
+            Kind::Punct(')') | Kind::Punct(']') | Kind::Punct('}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(if i > 1 { args + 1 } else { 0 });
+                }
+            }
+            Kind::Punct(',') if depth == 1 => args += 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+// Replaces everything but line breaks with spaces.
+fn blank(src: &str) -> String {
+    src.chars()
+        .map(|c| if c == '\n' { c } else { ' ' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_precision() {
+        assert_eq!(
+            translate("precision highp float;\nmediump vec3 c;\nlowp int n;\n"),
+            "                      \n vec3 c;\n int n;\n"
+        );
+    }
+
+    #[test]
+    fn strips_version_and_es_extensions() {
+        let src = "#version 300 es\n\
+                   #extension GL_OES_standard_derivatives : enable\n\
+                   #extension GL_EXT_shader_texture_lod : enable\n\
+                   #extension GL_ARB_gpu_shader5 : enable\n\
+                   void main() {}\n";
+        assert_eq!(
+            translate(src),
+            "\n\n\n#extension GL_ARB_gpu_shader5 : enable\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn renames_texture_functions() {
+        assert_eq!(
+            translate("texture2D(iChannel0, uv) + textureCube(iChannel1, dir)"),
+            "texture(iChannel0, uv) + texture(iChannel1, dir)"
+        );
+        assert_eq!(
+            translate("texture2DLodEXT(iChannel0, uv, 0.0)"),
+            "textureLod(iChannel0, uv, 0.0)"
+        );
+        // Fields and identifiers that aren't called keep their name.
+        assert_eq!(
+            translate("s.texture2D(uv); float texture2D;"),
+            "s.texture2D(uv); float texture2D;"
+        );
+    }
+
+    #[test]
+    fn renames_mod() {
+        assert_eq!(
+            translate("float m = mod(model, 2.0); mat4 model; vec2 f = modf(x, i);"),
+            "float m = _compat_mod(model, 2.0); mat4 model; vec2 f = modf(x, i);"
+        );
+    }
+
+    #[test]
+    fn keeps_defined_names() {
+        let src = "#define mod(x, y) (x - y * floor(x / y))\n\
+                   #define texture2D texture\n\
+                   float a = mod(1.0, 2.0);\n\
+                   vec4 c = texture2D(iChannel0, uv);\n";
+        assert_eq!(translate(src), src);
+    }
+
+    #[test]
+    fn sizes_array_constructors() {
+        assert_eq!(
+            translate("float a[] = float[](1.0, 2.0, 3.0);"),
+            "float a[] = float[3](1.0, 2.0, 3.0);"
+        );
+        assert_eq!(
+            translate("vec2[](vec2(0.0, 1.0), vec2(2.0))"),
+            "vec2[2](vec2(0.0, 1.0), vec2(2.0))"
+        );
+        assert_eq!(
+            translate("float[4](1.0, 2.0, 3.0, 4.0)"),
+            "float[4](1.0, 2.0, 3.0, 4.0)"
+        );
+    }
+
+    #[test]
+    fn keeps_line_count() {
+        let src = "#version 300 es\n\
+                   precision\n  highp\n  float;\n\
+                   #extension GL_OES_standard_derivatives : enable\n\
+                   /* texture2D(\n iChannel0) */\n\
+                   void mainImage(out vec4 o, in vec2 p) {\n\
+                   \x20   o = texture2D(iChannel0, p) * mod(p.x, 2.0);\n\
+                   }\n";
+        let translated = translate(src);
+        assert_eq!(translated.lines().count(), src.lines().count());
+        assert_eq!(
+            translated.lines().nth(8),
+            Some("    o = texture(iChannel0, p) * _compat_mod(p.x, 2.0);")
+        );
+    }
+}
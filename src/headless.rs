@@ -0,0 +1,234 @@
+use error::{self, HeadlessContextError, ReadPixelsError};
+
+use gfx;
+use gfx::memory::{Bind, Typed, Usage};
+use gfx::texture;
+use gfx::traits::FactoryExt;
+use gfx::Factory;
+use gfx_device_gl;
+
+use std::os::raw::c_void;
+
+pub type Resources = gfx_device_gl::Resources;
+pub type Encoder = gfx::Encoder<Resources, gfx_device_gl::CommandBuffer>;
+
+// An offscreen color target that can be read back to the CPU.
+pub struct ReadbackTarget<T: gfx::format::RenderFormat + gfx::format::TextureFormat> {
+    pub texture: gfx::handle::Texture<Resources, T::Surface>,
+    pub target: gfx::handle::RenderTargetView<Resources, T>,
+}
+
+// An OpenGL context (and gfx device) with no window attached.
+pub struct Headless {
+    pub device: gfx_device_gl::Device,
+    pub factory: gfx_device_gl::Factory,
+
+    // Must outlive the device and factory.
+    _context: Context,
+}
+
+impl Headless {
+    pub fn new() -> error::Result<Headless> {
+        let (context, device, factory) = create_context()?;
+        Ok(Headless {
+            device,
+            factory,
+            _context: context,
+        })
+    }
+
+    pub fn create_encoder(&mut self) -> Encoder {
+        gfx::Encoder::from(self.factory.create_command_buffer())
+    }
+
+    pub fn create_readback_target<T>(
+        &mut self,
+        width: texture::Size,
+        height: texture::Size,
+    ) -> error::Result<ReadbackTarget<T>>
+    where
+        T: gfx::format::RenderFormat + gfx::format::TextureFormat,
+    {
+        let kind = texture::Kind::D2(width, height, texture::AaMode::Single);
+        let bind = Bind::RENDER_TARGET | Bind::TRANSFER_SRC;
+        let channel = <T::Channel as gfx::format::ChannelTyped>::get_channel_type();
+        let texture =
+            self.factory
+                .create_texture::<T::Surface>(kind, 1, bind, Usage::Data, Some(channel))?;
+        let target = self
+            .factory
+            .view_texture_as_render_target(&texture, 0, None)?;
+        Ok(ReadbackTarget { texture, target })
+    }
+
+    // Flushes the encoder and reads back the target's pixels, bottom row first. `P` must match
+    // the layout of a texel of `T`, e.g. [u8; 4] for Rgba8 or [f32; 4] for Rgba32F.
+    pub fn read_pixels<T, P>(
+        &mut self,
+        encoder: &mut Encoder,
+        target: &ReadbackTarget<T>,
+    ) -> error::Result<Vec<P>>
+    where
+        T: gfx::format::RenderFormat + gfx::format::TextureFormat,
+        P: Copy,
+    {
+        let info = target.texture.get_info().to_image_info(0);
+        let download = self
+            .factory
+            .create_download_buffer::<P>(info.get_texel_count())?;
+        encoder
+            .copy_texture_to_buffer_raw(
+                target.texture.raw(),
+                None,
+                info.convert(T::get_format()),
+                download.raw(),
+                0,
+            )
+            .map_err(|err| ReadPixelsError::new(format!("{:?}", err)))?;
+        encoder.flush(&mut self.device);
+
+        let pixels = self.factory.read_mapping(&download)?.to_vec();
+        Ok(pixels)
+    }
+}
+
+// Everything needed to keep the underlying GL context alive. Never read, only dropped.
+#[allow(dead_code)]
+enum Context {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    Egl(Box<egl_surfaceless::EglContext>),
+    Glutin(
+        Box<(
+            glutin::Context<glutin::PossiblyCurrent>,
+            glutin::event_loop::EventLoop<()>,
+        )>,
+    ),
+}
+
+fn create_context() -> error::Result<(Context, gfx_device_gl::Device, gfx_device_gl::Factory)> {
+    // Prefer EGL's surfaceless platform, which works without any display server (e.g. on CI).
+    #[cfg(all(unix, not(target_os = "macos")))]
+    match egl_surfaceless::EglContext::new() {
+        Ok(context) => {
+            let (device, factory) =
+                gfx_device_gl::create(|s| context.get_proc_address(s) as *const c_void);
+            return Ok((Context::Egl(Box::new(context)), device, factory));
+        }
+        Err(e) => info!("EGL surfaceless context unavailable ({}), trying glutin", e),
+    }
+
+    let event_loop = new_event_loop()?;
+    let context = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 2)))
+        .with_gl_profile(glutin::GlProfile::Core)
+        .build_headless(&event_loop, glutin::dpi::PhysicalSize::new(1, 1))
+        .map_err(|e| HeadlessContextError::new(e.to_string()))?;
+    let context = unsafe { context.make_current() }
+        .map_err(|(_, e)| HeadlessContextError::new(e.to_string()))?;
+    let (device, factory) = gfx_device_gl::create(|s| context.get_proc_address(s) as *const c_void);
+
+    Ok((
+        Context::Glutin(Box::new((context, event_loop))),
+        device,
+        factory,
+    ))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn new_event_loop() -> error::Result<glutin::event_loop::EventLoop<()>> {
+    use glutin::platform::unix::EventLoopExtUnix;
+    use std::env;
+
+    // Winit can't be relied on to fail gracefully without a display, so check for one first.
+    if env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err(HeadlessContextError::new("no EGL or display available".to_string()).into());
+    }
+
+    // Unlike EventLoop::new(), this works off the main thread.
+    Ok(glutin::event_loop::EventLoop::new_any_thread())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn new_event_loop() -> error::Result<glutin::event_loop::EventLoop<()>> {
+    Ok(glutin::event_loop::EventLoop::new())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod egl_surfaceless {
+    use error::{self, HeadlessContextError};
+    use khronos_egl as egl;
+
+    // From EGL_MESA_platform_surfaceless.
+    const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+    pub struct EglContext {
+        egl: egl::DynamicInstance<egl::EGL1_5>,
+        display: egl::Display,
+        context: egl::Context,
+    }
+
+    impl EglContext {
+        pub fn new() -> error::Result<EglContext> {
+            let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+                .map_err(|e| HeadlessContextError::new(e.to_string()))?;
+            let err = |e: egl::Error| HeadlessContextError::new(e.to_string());
+
+            let display = egl
+                .get_platform_display(
+                    PLATFORM_SURFACELESS_MESA,
+                    egl::DEFAULT_DISPLAY,
+                    &[egl::ATTRIB_NONE],
+                )
+                .map_err(err)?;
+            egl.initialize(display).map_err(err)?;
+
+            let config_attribs = [
+                egl::SURFACE_TYPE,
+                egl::PBUFFER_BIT,
+                egl::RENDERABLE_TYPE,
+                egl::OPENGL_BIT,
+                egl::NONE,
+            ];
+            let config = egl
+                .choose_first_config(display, &config_attribs)
+                .map_err(err)?
+                .ok_or_else(|| HeadlessContextError::new("no suitable EGL config".to_string()))?;
+
+            egl.bind_api(egl::OPENGL_API).map_err(err)?;
+            let context_attribs = [
+                egl::CONTEXT_MAJOR_VERSION,
+                3,
+                egl::CONTEXT_MINOR_VERSION,
+                2,
+                egl::CONTEXT_OPENGL_PROFILE_MASK,
+                egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                egl::NONE,
+            ];
+            let context = egl
+                .create_context(display, config, None, &context_attribs)
+                .map_err(err)?;
+            egl.make_current(display, None, None, Some(context))
+                .map_err(err)?;
+
+            Ok(EglContext {
+                egl,
+                display,
+                context,
+            })
+        }
+
+        pub fn get_proc_address(&self, name: &str) -> *const () {
+            self.egl
+                .get_proc_address(name)
+                .map_or(::std::ptr::null(), |f| f as *const ())
+        }
+    }
+
+    impl Drop for EglContext {
+        fn drop(&mut self) {
+            let _ = self.egl.make_current(self.display, None, None, None);
+            let _ = self.egl.destroy_context(self.display, self.context);
+            let _ = self.egl.terminate(self.display);
+        }
+    }
+}
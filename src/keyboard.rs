@@ -0,0 +1,255 @@
+// Keyboard state as shadertoy.com exposes it to shaders: a 256x3 texture indexed by JavaScript
+// keycode, whose first row is 1 while a key is down, second row is 1 on the frame it was pressed,
+// and third row toggles each time it's pressed.
+
+use glutin::event::VirtualKeyCode;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 3;
+
+#[derive(Clone)]
+pub struct Keyboard {
+    down: [bool; WIDTH],
+    pressed: [bool; WIDTH],
+    toggled: [bool; WIDTH],
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard {
+            down: [false; WIDTH],
+            pressed: [false; WIDTH],
+            toggled: [false; WIDTH],
+        }
+    }
+
+    // Records a key going down. Repeats while it's held don't count as presses.
+    pub fn press(&mut self, keycode: u8) {
+        let key = usize::from(keycode);
+        if !self.down[key] {
+            self.down[key] = true;
+            self.pressed[key] = true;
+            self.toggled[key] = !self.toggled[key];
+        }
+    }
+
+    pub fn release(&mut self, keycode: u8) {
+        self.down[usize::from(keycode)] = false;
+    }
+
+    // Releases every key, e.g. when the window loses focus and won't see them come back up.
+    pub fn release_all(&mut self) {
+        self.down = [false; WIDTH];
+    }
+
+    // Called after each frame, so presses only last for the frame they happened before.
+    pub fn end_frame(&mut self) {
+        self.pressed = [false; WIDTH];
+    }
+
+    // Texels of the keyboard texture, top row first, with the state in the red channel.
+    pub fn texels(&self) -> Vec<[u8; 4]> {
+        [&self.down, &self.pressed, &self.toggled]
+            .iter()
+            .flat_map(|row| row.iter())
+            .map(|&on| [if on { 255 } else { 0 }, 0, 0, 255])
+            .collect()
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
+// The JavaScript keycode of a key (KeyboardEvent.keyCode), which shaders index the keyboard
+// texture with. None for keys browsers don't report.
+pub fn js_keycode(key: VirtualKeyCode) -> Option<u8> {
+    use glutin::event::VirtualKeyCode::*;
+
+    let keycode = match key {
+        Back => 8,
+        Tab => 9,
+        Return | NumpadEnter => 13,
+        LShift | RShift => 16,
+        LControl | RControl => 17,
+        LAlt | RAlt => 18,
+        Pause => 19,
+        Capital => 20,
+        Escape => 27,
+        Space => 32,
+        PageUp => 33,
+        PageDown => 34,
+        End => 35,
+        Home => 36,
+        Left => 37,
+        Up => 38,
+        Right => 39,
+        Down => 40,
+        Snapshot => 44,
+        Insert => 45,
+        Delete => 46,
+        Key0 => 48,
+        Key1 => 49,
+        Key2 => 50,
+        Key3 => 51,
+        Key4 => 52,
+        Key5 => 53,
+        Key6 => 54,
+        Key7 => 55,
+        Key8 => 56,
+        Key9 => 57,
+        A => 65,
+        B => 66,
+        C => 67,
+        D => 68,
+        E => 69,
+        F => 70,
+        G => 71,
+        H => 72,
+        I => 73,
+        J => 74,
+        K => 75,
+        L => 76,
+        M => 77,
+        N => 78,
+        O => 79,
+        P => 80,
+        Q => 81,
+        R => 82,
+        S => 83,
+        T => 84,
+        U => 85,
+        V => 86,
+        W => 87,
+        X => 88,
+        Y => 89,
+        Z => 90,
+        LWin => 91,
+        RWin => 92,
+        Apps => 93,
+        Numpad0 => 96,
+        Numpad1 => 97,
+        Numpad2 => 98,
+        Numpad3 => 99,
+        Numpad4 => 100,
+        Numpad5 => 101,
+        Numpad6 => 102,
+        Numpad7 => 103,
+        Numpad8 => 104,
+        Numpad9 => 105,
+        NumpadMultiply | Asterisk => 106,
+        NumpadAdd => 107,
+        NumpadSubtract => 109,
+        NumpadDecimal => 110,
+        NumpadDivide => 111,
+        F1 => 112,
+        F2 => 113,
+        F3 => 114,
+        F4 => 115,
+        F5 => 116,
+        F6 => 117,
+        F7 => 118,
+        F8 => 119,
+        F9 => 120,
+        F10 => 121,
+        F11 => 122,
+        F12 => 123,
+        Numlock => 144,
+        Scroll => 145,
+        Semicolon | Colon => 186,
+        Equals | Plus => 187,
+        Comma | NumpadComma => 188,
+        Minus => 189,
+        Period => 190,
+        Slash => 191,
+        Grave => 192,
+        LBracket => 219,
+        Backslash => 220,
+        RBracket => 221,
+        Apostrophe => 222,
+        _ => return None,
+    };
+    Some(keycode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The down, pressed and toggled rows of the key in the texture.
+    fn rows(keyboard: &Keyboard, keycode: u8) -> [bool; 3] {
+        let texels = keyboard.texels();
+        let row = |n: usize| texels[n * WIDTH + usize::from(keycode)][0] == 255;
+        [row(0), row(1), row(2)]
+    }
+
+    #[test]
+    fn tracks_presses() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.texels().len(), WIDTH * HEIGHT);
+        assert_eq!(rows(&keyboard, 65), [false, false, false]);
+
+        keyboard.press(65);
+        assert_eq!(rows(&keyboard, 65), [true, true, true]);
+        assert_eq!(rows(&keyboard, 66), [false, false, false]);
+
+        // Key repeat doesn't press the key again.
+        keyboard.end_frame();
+        keyboard.press(65);
+        assert_eq!(rows(&keyboard, 65), [true, false, true]);
+
+        keyboard.release(65);
+        assert_eq!(rows(&keyboard, 65), [false, false, true]);
+
+        // Pressing it again toggles it off.
+        keyboard.press(65);
+        assert_eq!(rows(&keyboard, 65), [true, true, false]);
+        keyboard.release(65);
+        keyboard.end_frame();
+        assert_eq!(rows(&keyboard, 65), [false, false, false]);
+    }
+
+    #[test]
+    fn presses_last_until_the_end_of_the_frame() {
+        // A key pressed and released between frames still reads as pressed for one frame.
+        let mut keyboard = Keyboard::new();
+        keyboard.press(32);
+        keyboard.release(32);
+        assert_eq!(rows(&keyboard, 32), [false, true, true]);
+        keyboard.end_frame();
+        assert_eq!(rows(&keyboard, 32), [false, false, true]);
+    }
+
+    #[test]
+    fn releases_all_keys() {
+        let mut keyboard = Keyboard::new();
+        keyboard.press(37);
+        keyboard.press(255);
+        keyboard.release_all();
+        assert_eq!(rows(&keyboard, 37), [false, true, true]);
+        assert_eq!(rows(&keyboard, 255), [false, true, true]);
+
+        // Keys held when focus was lost can be pressed again.
+        keyboard.end_frame();
+        keyboard.press(37);
+        assert_eq!(rows(&keyboard, 37), [true, true, false]);
+    }
+
+    #[test]
+    fn maps_keys_to_browser_keycodes() {
+        use glutin::event::VirtualKeyCode::*;
+
+        assert_eq!(js_keycode(A), Some(65));
+        assert_eq!(js_keycode(Z), Some(90));
+        assert_eq!(js_keycode(Key0), Some(48));
+        assert_eq!(js_keycode(Key9), Some(57));
+        assert_eq!(js_keycode(Left), Some(37));
+        assert_eq!(js_keycode(Up), Some(38));
+        assert_eq!(js_keycode(Right), Some(39));
+        assert_eq!(js_keycode(Down), Some(40));
+        assert_eq!(js_keycode(Space), Some(32));
+        assert_eq!(js_keycode(LShift), js_keycode(RShift));
+    }
+}
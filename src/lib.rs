@@ -0,0 +1,52 @@
+//! Library behind the shadertoy-rs desktop client, for embedding a Shadertoy-compatible shader
+//! player in other programs.
+//!
+//! A shader is described by a `ShaderConfig` (the source of each pass and what its channels read).
+//! `Renderer` draws it with any gfx backend, into a caller-provided window or render target, while
+//! `ShaderPlayer` renders it offscreen and hands back the pixels:
+//!
+//! ```no_run
+//! use shadertoy_rs::{ShaderConfig, ShaderPlayer};
+//!
+//! let config = ShaderConfig::new(
+//!     "void mainImage(out vec4 c, in vec2 f) { c = vec4(f / iResolution.xy, 0.0, 1.0); }",
+//! );
+//! let mut player = ShaderPlayer::new(&config, 640, 360).unwrap();
+//! player.renderer().set_time(1.0, 1.0 / 60.0);
+//! let pixels = player.render().unwrap();
+//! assert_eq!(pixels.len(), 640 * 360);
+//! ```
+
+#[macro_use]
+extern crate gfx;
+extern crate gfx_device_gl;
+extern crate glutin;
+extern crate image;
+#[macro_use]
+extern crate log;
+extern crate anyhow;
+extern crate chrono;
+#[macro_use]
+extern crate serde;
+#[cfg(all(unix, not(target_os = "macos")))]
+extern crate khronos_egl;
+extern crate serde_json;
+extern crate toml;
+
+pub mod diagnostics;
+pub mod error;
+pub mod glsl;
+pub mod headless;
+pub mod keyboard;
+pub mod loader;
+pub mod mouse;
+pub mod overlay;
+pub mod pass;
+pub mod player;
+pub mod project;
+pub mod renderer;
+
+pub use pass::{BufferId, ChannelBinding, ChannelInput, PassId};
+pub use player::ShaderPlayer;
+pub use project::{Manifest, Project};
+pub use renderer::{Renderer, ShaderConfig, TextureSource, VolumeSource};
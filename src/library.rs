@@ -0,0 +1,35 @@
+use cache::ShaderCache;
+use error;
+
+// Prints the downloaded shaders matching all the search terms (or every one if there are none),
+// one per line.
+pub fn list(terms: &[String]) -> error::Result<()> {
+    let shaders: Vec<_> = ShaderCache::new()
+        .list()
+        .into_iter()
+        .filter(|meta| meta.matches(terms))
+        .collect();
+
+    if shaders.is_empty() {
+        println!("No downloaded shaders found");
+        return Ok(());
+    }
+
+    for meta in shaders {
+        let tags = if meta.tags.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", meta.tags.join(", "))
+        };
+        println!(
+            "{}  {:10}  {} by {}{}",
+            meta.id,
+            meta.date.as_deref().unwrap_or("-"),
+            meta.name,
+            meta.author,
+            tags
+        );
+    }
+
+    Ok(())
+}
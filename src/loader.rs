@@ -1,15 +1,18 @@
-use argvalues::ArgValues;
+use diagnostics::ComposedSource;
 use error::{
-    self, FindExampleShaderError, LoadShaderError, UnsupportedUniformError, UNSUPPORTED_UNIFORMS,
+    self, FindExampleShaderError, InvalidCubemapError, InvalidTextureError, InvalidVolumeError,
+    LoadShaderError,
 };
-use runner::TextureId;
+use glsl;
+use renderer::{ChannelTexture, TextureId, TextureKind, TextureSource, VolumeSource};
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Read};
+use std::mem;
 use std::path::Path;
 
 use gfx;
-use image;
+use image::{self, GenericImage};
 
 // Default shaders.
 pub static DEFAULT_VERT_SRC_BUF: &[u8] = include_bytes!("../shaders/default.vert");
@@ -31,105 +34,773 @@ const PREFIX: &str = "
 
     uniform float     iGlobalTime;
     uniform float     iTime;
+    uniform float     iTimeDelta;
+    uniform float     iFrameRate;
+    uniform vec4      iDate;
     uniform vec3      iResolution;
     uniform vec4      iMouse;
     uniform int       iFrame;
-    uniform sampler2D iChannel0;
-    uniform sampler2D iChannel1;
-    uniform sampler2D iChannel2;
-    uniform sampler2D iChannel3;
+    uniform float     iSampleRate;
 
     in vec2 fragCoord;
     out vec4 fragColor;
 ";
 
+// Sound shader prefix. The sound pass renders one stereo sample per pixel, starting at the
+// sample at iBlockOffset seconds.
+const SOUND_PREFIX: &str = "
+    #version 150 core
+
+    uniform float     iSampleRate;
+    uniform float     iBlockOffset;
+    uniform vec4      iDate;
+
+    out vec4 fragColor;
+";
+
+// Channel uniforms, shared by all passes, after the samplers declared by channel_samplers().
+const CHANNEL_PREFIX: &str = "
+    uniform vec3      iChannelResolution0;
+    uniform vec3      iChannelResolution1;
+    uniform vec3      iChannelResolution2;
+    uniform vec3      iChannelResolution3;
+    uniform float     iChannelTime0;
+    uniform float     iChannelTime1;
+    uniform float     iChannelTime2;
+    uniform float     iChannelTime3;
+
+    // Filled in from the uniforms above at the start of main().
+    vec3  iChannelResolution[4];
+    float iChannelTime[4];
+
+    void _initChannelArrays() {
+        iChannelResolution = vec3[4](
+            iChannelResolution0, iChannelResolution1, iChannelResolution2, iChannelResolution3);
+        iChannelTime = float[4](iChannelTime0, iChannelTime1, iChannelTime2, iChannelTime3);
+    }
+";
+
 // Fragment shader suffix.
 const SUFFIX: &str = "
     void main() {
+        _initChannelArrays();
+
         mainImage(fragColor, fragCoord);
     }
 ";
 
+// Cube A suffix. The pass is drawn once per face of the cubemap, with _cubeFace counting them in
+// the order +X, -X, +Y, -Y, +Z, -Z. Each fragment gets the direction through its texel of the face,
+// laid out as OpenGL samples cubemaps, from the cubemap's center.
+const CUBE_SUFFIX: &str = "
+    uniform int _cubeFace;
+
+    void main() {
+        _initChannelArrays();
+
+        vec2 st = fragCoord / iResolution.xy * 2.0 - 1.0;
+        vec3 rayDirs[6] = vec3[6](
+            vec3(1.0, -st.y, -st.x),
+            vec3(-1.0, -st.y, st.x),
+            vec3(st.x, 1.0, st.y),
+            vec3(st.x, -1.0, -st.y),
+            vec3(st.x, -st.y, 1.0),
+            vec3(-st.x, -st.y, -1.0));
+        mainCubemap(fragColor, fragCoord, vec3(0.0), normalize(rayDirs[_cubeFace]));
+    }
+";
+
+// Sound shader suffix. The block width (512) must match sound::BLOCK_SIZE. Sample indices are
+// counted from _blockStart, the index of the block's first sample, since iBlockOffset can't hold
+// every index once it's a few minutes in.
+const SOUND_SUFFIX: &str = "
+    uniform int _blockStart;
+
+    void main() {
+        _initChannelArrays();
+
+        int offset = int(gl_FragCoord.y) * 512 + int(gl_FragCoord.x);
+        int samp = _blockStart + offset;
+        fragColor = vec4(mainSound(samp, iBlockOffset + float(offset) / iSampleRate), 0.0, 1.0);
+    }
+";
+
+// Sound shader suffix for the older mainSound(float time) signature.
+const LEGACY_SOUND_SUFFIX: &str = "
+    void main() {
+        _initChannelArrays();
+
+        int offset = int(gl_FragCoord.y) * 512 + int(gl_FragCoord.x);
+        fragColor = vec4(mainSound(iBlockOffset + float(offset) / iSampleRate), 0.0, 1.0);
+    }
+";
+
+// Declares iChannel0-3 with the type of sampler for the texture each channel reads.
+fn channel_samplers(channel_kinds: [TextureKind; 4]) -> String {
+    channel_kinds
+        .iter()
+        .enumerate()
+        .map(|(channel, kind)| {
+            format!(
+                "    uniform {:<9} iChannel{};\n",
+                kind.sampler_type(),
+                channel
+            )
+        })
+        .collect()
+}
+
 fn return_load_shader_error<E>(shaderpath: &str, err: io::Error) -> error::Result<E> {
     Err(LoadShaderError::new(shaderpath, err).into())
 }
 
-pub fn format_shader_src(src: &str) -> Vec<u8> {
-    format!("{}\n{}\n{}", PREFIX, src, SUFFIX).into_bytes()
+// Wraps a pass's source in the prefix and suffix, splicing in the common source shared by all
+// passes (which may be empty). Both are translated from GLSL ES first, and named for error
+// messages. Channels are declared as samplers for the given kinds of textures.
+pub fn format_shader_src(
+    channel_kinds: [TextureKind; 4],
+    common_name: &str,
+    common_src: &str,
+    name: &str,
+    src: &str,
+) -> ComposedSource {
+    compose(
+        PREFIX,
+        channel_kinds,
+        common_name,
+        common_src,
+        name,
+        src,
+        SUFFIX,
+    )
 }
 
-pub fn load_fragment_shader(av: &ArgValues) -> error::Result<Vec<u8>> {
-    let frag_src_str = if let Some(ref example) = av.examplename {
-        match example.as_ref() {
-            "seascape" => EXAMPLE_SEASCAPE_STR.to_string(),
-            "elemental-ring" => EXAMPLE_ELEMENTAL_RING_STR.to_string(),
-            _ => return Err(FindExampleShaderError::new(example.as_str()).into()),
-        }
-    } else {
-        // Read fragment shader from file into String buffer.
-        match av.shaderpath {
-            Some(ref shaderpath) => {
-                let mut frag_src_str = String::new();
-
-                File::open(&Path::new(&shaderpath))
-                    .or_else(|err| return_load_shader_error(shaderpath, err))?
-                    .read_to_string(&mut frag_src_str)
-                    .or_else(|err| return_load_shader_error(shaderpath, err))?;
+// Like format_shader_src, but for the Cube A pass defining mainCubemap instead of mainImage.
+pub fn format_cube_shader_src(
+    channel_kinds: [TextureKind; 4],
+    common_name: &str,
+    common_src: &str,
+    name: &str,
+    src: &str,
+) -> ComposedSource {
+    compose(
+        PREFIX,
+        channel_kinds,
+        common_name,
+        common_src,
+        name,
+        src,
+        CUBE_SUFFIX,
+    )
+}
 
-                frag_src_str
-            }
-            None => String::from(DEFAULT_FRAG_SRC_STR),
-        }
+// Like format_shader_src, but for a sound pass defining mainSound instead of mainImage.
+pub fn format_sound_shader_src(
+    channel_kinds: [TextureKind; 4],
+    common_name: &str,
+    common_src: &str,
+    name: &str,
+    src: &str,
+) -> ComposedSource {
+    let suffix = if uses_legacy_main_sound(src) {
+        LEGACY_SOUND_SUFFIX
+    } else {
+        SOUND_SUFFIX
     };
+    compose(
+        SOUND_PREFIX,
+        channel_kinds,
+        common_name,
+        common_src,
+        name,
+        src,
+        suffix,
+    )
+}
 
-    let unsupported_uniforms: Vec<String> = UNSUPPORTED_UNIFORMS
-        .iter()
-        .map(|s| s.to_string())
-        .filter(|uu| frag_src_str.contains(uu))
-        .collect();
+fn compose(
+    prefix: &str,
+    channel_kinds: [TextureKind; 4],
+    common_name: &str,
+    common_src: &str,
+    name: &str,
+    src: &str,
+    suffix: &str,
+) -> ComposedSource {
+    let mut composed = ComposedSource::new();
+    composed.push_generated(prefix);
+    composed.push_generated(glsl::COMPAT_PREFIX);
+    composed.push_generated(&channel_samplers(channel_kinds));
+    composed.push_generated(CHANNEL_PREFIX);
+    composed.push_source(common_name, common_src);
+    composed.push_source(name, src);
+    composed.push_generated(suffix);
+    composed
+}
 
-    if unsupported_uniforms.is_empty() {
-        Ok(format_shader_src(&frag_src_str))
-    } else {
-        Err(UnsupportedUniformError::new(unsupported_uniforms).into())
+// True if mainSound takes just the time, as in shaders written before mainSound(int, float).
+fn uses_legacy_main_sound(src: &str) -> bool {
+    src.match_indices("mainSound")
+        .filter_map(|(i, name)| src[i + name.len()..].trim_start().strip_prefix('('))
+        .any(|params| params.trim_start().starts_with("float"))
+}
+
+// Returns the source of one of the bundled example shaders.
+pub fn example_src(example: &str) -> error::Result<&'static str> {
+    match example {
+        "seascape" => Ok(EXAMPLE_SEASCAPE_STR),
+        "elemental-ring" => Ok(EXAMPLE_ELEMENTAL_RING_STR),
+        _ => Err(FindExampleShaderError::new(example).into()),
     }
 }
 
+pub fn read_shader_file(shaderpath: &str) -> error::Result<String> {
+    let mut frag_src_str = String::new();
+
+    File::open(Path::new(shaderpath))
+        .or_else(|err| return_load_shader_error(shaderpath, err))?
+        .read_to_string(&mut frag_src_str)
+        .or_else(|err| return_load_shader_error(shaderpath, err))?;
+
+    Ok(frag_src_str)
+}
+
 pub fn load_vertex_shader() -> Vec<u8> {
     DEFAULT_VERT_SRC_BUF.to_vec()
 }
 
 pub fn load_texture<F, R>(
-    id: &TextureId,
-    texpath: &Option<String>,
+    source: &TextureSource,
     factory: &mut F,
-) -> error::Result<gfx::handle::ShaderResourceView<R, [f32; 4]>>
+) -> error::Result<ChannelTexture<R>>
 where
     F: gfx::Factory<R>,
     R: gfx::Resources,
 {
-    use gfx::format::Rgba8;
+    use gfx::format::{Rgba8, Srgba8};
     use gfx::texture::Mipmap;
 
-    let default_buf = if texpath.is_some() {
-        None
+    let mut srgb = false;
+    let img = match *source {
+        TextureSource::Cubemap {
+            ref faces,
+            vflip,
+            srgb,
+        } => return load_cubemap(faces, vflip, srgb, factory),
+        TextureSource::Volume(ref volume) => return load_volume(volume, factory),
+        TextureSource::Default(id) => {
+            let default_buf = match id {
+                TextureId::Zero => DEFAULT_TEXTURE0_BUF,
+                TextureId::One => DEFAULT_TEXTURE1_BUF,
+                TextureId::Two => DEFAULT_TEXTURE2_BUF,
+                TextureId::Three => DEFAULT_TEXTURE3_BUF,
+            };
+            image::load_from_memory(default_buf)?.flipv().to_rgba()
+        }
+        TextureSource::File {
+            ref path,
+            vflip,
+            srgb: file_srgb,
+        } => {
+            let img = image::open(path)?;
+            srgb = file_srgb;
+            if vflip {
+                img.flipv().to_rgba()
+            } else {
+                img.to_rgba()
+            }
+        }
+        TextureSource::Rgba8 {
+            width,
+            height,
+            ref pixels,
+        } => image::RgbaImage::from_raw(width, height, pixels.clone())
+            .map(|img| image::imageops::flip_vertical(&img))
+            .ok_or(InvalidTextureError::new(width, height, pixels.len()))?,
+    };
+
+    let (w, h) = img.dimensions();
+    let kind = gfx::texture::Kind::D2(w as u16, h as u16, gfx::texture::AaMode::Single);
+    let (_, view) = if srgb {
+        factory.create_texture_immutable_u8::<Srgba8>(kind, Mipmap::Allocated, &[&img])?
     } else {
-        match *id {
-            TextureId::Zero => Some(DEFAULT_TEXTURE0_BUF),
-            TextureId::One => Some(DEFAULT_TEXTURE1_BUF),
-            TextureId::Two => Some(DEFAULT_TEXTURE2_BUF),
-            TextureId::Three => Some(DEFAULT_TEXTURE3_BUF),
+        factory.create_texture_immutable_u8::<Rgba8>(kind, Mipmap::Allocated, &[&img])?
+    };
+    Ok((view, [w as f32, h as f32, 1.0]))
+}
+
+// Loads a cubemap from its six faces, or from a single image with them laid out as a cross or a
+// strip.
+fn load_cubemap<F, R>(
+    paths: &[String],
+    vflip: bool,
+    srgb: bool,
+    factory: &mut F,
+) -> error::Result<ChannelTexture<R>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    use gfx::format::{Rgba8, Srgba8};
+    use gfx::texture::Mipmap;
+
+    let faces = match *paths {
+        [ref path] => split_cubemap(path, image::open(path)?)?,
+        _ if paths.len() == 6 => paths
+            .iter()
+            .map(|path| Ok(image::open(path)?))
+            .collect::<error::Result<_>>()?,
+        _ => {
+            let reason = format!("expected 1 or 6 images, got {}", paths.len());
+            return Err(InvalidCubemapError::new(&paths.join(", "), reason).into());
         }
     };
-    let img = if let Some(default_buf) = default_buf {
-        image::load_from_memory(default_buf)?.flipv().to_rgba()
+
+    let size = faces[0].width();
+    let mut data = Vec::new();
+    for (path, face) in paths.iter().cycle().zip(&faces) {
+        if face.dimensions() != (size, size) {
+            let (w, h) = face.dimensions();
+            let reason = format!("faces must be square and the same size, got {}x{}", w, h);
+            return Err(InvalidCubemapError::new(path, reason).into());
+        }
+        data.push(if vflip {
+            face.flipv().to_rgba().into_raw()
+        } else {
+            face.to_rgba().into_raw()
+        });
+    }
+    let data: Vec<&[u8]> = data.iter().map(Vec::as_slice).collect();
+
+    let kind = gfx::texture::Kind::Cube(size as u16);
+    let (_, view) = if srgb {
+        factory.create_texture_immutable_u8::<Srgba8>(kind, Mipmap::Allocated, &data)?
     } else {
-        image::open(&texpath.clone().unwrap())?.flipv().to_rgba()
+        factory.create_texture_immutable_u8::<Rgba8>(kind, Mipmap::Allocated, &data)?
     };
+    Ok((view, [size as f32, size as f32, 1.0]))
+}
 
+// Cuts the six faces (+X, -X, +Y, -Y, +Z, -Z) out of a single image. In a horizontal cross, the
+// middle row is -X, +Z, +X, -Z with +Y above +Z and -Y below it. A vertical cross has -Z below -Y
+// instead, upside down. Strips have the faces in order.
+fn split_cubemap(
+    path: &str,
+    mut img: image::DynamicImage,
+) -> error::Result<Vec<image::DynamicImage>> {
+    // Where each face is, in units of faces, and whether it's upside down.
     let (w, h) = img.dimensions();
-    let kind = gfx::texture::Kind::D2(w as u16, h as u16, gfx::texture::AaMode::Single);
-    let (_, view) =
-        factory.create_texture_immutable_u8::<Rgba8>(kind, Mipmap::Allocated, &[&img])?;
-    Ok(view)
+    let (size, cells) = if w * 3 == h * 4 {
+        let cross = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        (w / 4, cross.map(|(x, y)| (x, y, false)))
+    } else if w * 4 == h * 3 {
+        let cross = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)];
+        (w / 3, cross.map(|(x, y)| (x, y, y == 3)))
+    } else if w == h * 6 {
+        (h, [0, 1, 2, 3, 4, 5].map(|i| (i, 0, false)))
+    } else if h == w * 6 {
+        (w, [0, 1, 2, 3, 4, 5].map(|i| (0, i, false)))
+    } else {
+        let reason = format!(
+            "a {}x{} image isn't a 4x3 or 3x4 cross or a 6x1 or 1x6 strip of faces",
+            w, h
+        );
+        return Err(InvalidCubemapError::new(path, reason).into());
+    };
+
+    Ok(cells
+        .iter()
+        .map(|&(x, y, upside_down)| {
+            let face = img.crop(x * size, y * size, size, size);
+            if upside_down {
+                face.rotate180()
+            } else {
+                face
+            }
+        })
+        .collect())
+}
+
+// Texels of a volume, with four channels each.
+enum VolumeTexels {
+    Unorm(Vec<u8>),
+    Float(Vec<f32>),
+}
+
+// Size of the header of a .bin volume, before its texels, and the signature it starts with.
+const BIN_HEADER_SIZE: usize = 20;
+const BIN_SIGNATURE: &[u8; 4] = b"BIN\0";
+
+// Loads a 3D texture.
+fn load_volume<F, R>(source: &VolumeSource, factory: &mut F) -> error::Result<ChannelTexture<R>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    use gfx::format::{Rgba32F, Rgba8};
+    use gfx::texture::Mipmap;
+
+    let (size, texels) = match *source {
+        VolumeSource::Bin(ref path) => read_bin_volume(path)?,
+        VolumeSource::Raw {
+            ref path,
+            size,
+            channels,
+        } => {
+            let data = read_volume_file(path)?;
+            let texels = expand_texels(path, size, channels, &data, |bytes| bytes[0], 255)?;
+            (size, VolumeTexels::Unorm(texels))
+        }
+        VolumeSource::Stacked { ref path, depth } => {
+            let img = image::open(path)?.to_rgba();
+            let (w, h) = img.dimensions();
+            if depth == 0 || h % depth != 0 {
+                let reason = format!("a {}x{} image can't be cut into {} slices", w, h, depth);
+                return Err(InvalidVolumeError::new(path, reason).into());
+            }
+            ([w, h / depth, depth], VolumeTexels::Unorm(img.into_raw()))
+        }
+        VolumeSource::Slices(ref paths) => {
+            let mut texels = Vec::new();
+            let mut first = None;
+            for path in paths {
+                let slice = image::open(path)?.to_rgba();
+                let (w, h) = slice.dimensions();
+                let (first_w, first_h) = *first.get_or_insert((w, h));
+                if (w, h) != (first_w, first_h) {
+                    let reason = format!(
+                        "slices must be the same size, got {}x{} after {}x{}",
+                        w, h, first_w, first_h
+                    );
+                    return Err(InvalidVolumeError::new(path, reason).into());
+                }
+                texels.extend(slice.into_raw());
+            }
+            let (w, h) = first.ok_or_else(|| {
+                InvalidVolumeError::new("with no slices", "expected at least one".to_string())
+            })?;
+            ([w, h, paths.len() as u32], VolumeTexels::Unorm(texels))
+        }
+    };
+
+    let [w, h, d] = size;
+    let kind = gfx::texture::Kind::D3(w as u16, h as u16, d as u16);
+    let view = match texels {
+        VolumeTexels::Unorm(ref texels) => {
+            factory
+                .create_texture_immutable_u8::<Rgba8>(kind, Mipmap::Allocated, &[texels])?
+                .1
+        }
+        VolumeTexels::Float(ref texels) => {
+            factory
+                .create_texture_immutable_u8::<Rgba32F>(
+                    kind,
+                    Mipmap::Allocated,
+                    &[gfx::memory::cast_slice(texels)],
+                )?
+                .1
+        }
+    };
+    Ok((view, [w as f32, h as f32, d as f32]))
+}
+
+fn read_volume_file(path: &str) -> error::Result<Vec<u8>> {
+    fs::read(path).map_err(|err| InvalidVolumeError::new(path, err.to_string()).into())
+}
+
+// Reads a volume in shadertoy.com's binary format (see VolumeSource::Bin).
+fn read_bin_volume(path: &str) -> error::Result<([u32; 3], VolumeTexels)> {
+    let data = read_volume_file(path)?;
+    if data.len() < BIN_HEADER_SIZE {
+        let reason = format!("{} bytes is too short for a header", data.len());
+        return Err(InvalidVolumeError::new(path, reason).into());
+    }
+    if data[..4] != BIN_SIGNATURE[..] {
+        return Err(InvalidVolumeError::new(path, "no BIN signature".to_string()).into());
+    }
+
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+    let size = [u32_at(4), u32_at(8), u32_at(12)];
+    let channels = data[16];
+    let format = u16::from_le_bytes([data[18], data[19]]);
+    let body = &data[BIN_HEADER_SIZE..];
+
+    let texels = match format {
+        0 => VolumeTexels::Unorm(expand_texels(
+            path,
+            size,
+            channels,
+            body,
+            |bytes| bytes[0],
+            255,
+        )?),
+        10 => VolumeTexels::Float(expand_texels(
+            path,
+            size,
+            channels,
+            body,
+            |bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            1.0,
+        )?),
+        _ => {
+            let reason = format!("unsupported texel format {}", format);
+            return Err(InvalidVolumeError::new(path, reason).into());
+        }
+    };
+    Ok((size, texels))
+}
+
+// Parses texels of 1-4 channels and pads them out to four, with missing colors 0 and alpha `one`.
+fn expand_texels<T, P>(
+    path: &str,
+    size: [u32; 3],
+    channels: u8,
+    data: &[u8],
+    parse: P,
+    one: T,
+) -> error::Result<Vec<T>>
+where
+    T: Copy + Default,
+    P: Fn(&[u8]) -> T,
+{
+    let channels = usize::from(channels);
+    if !(1..=4).contains(&channels) {
+        let reason = format!("expected 1 to 4 channels, got {}", channels);
+        return Err(InvalidVolumeError::new(path, reason).into());
+    }
+
+    // The size comes from the file, so it may be too big for a texture or to even count the bytes.
+    if size.iter().any(|&n| n == 0 || n > u32::from(u16::MAX)) {
+        let reason = format!("can't make a {}x{}x{} texture", size[0], size[1], size[2]);
+        return Err(InvalidVolumeError::new(path, reason).into());
+    }
+    let width = mem::size_of::<T>();
+    let len = size
+        .iter()
+        .try_fold(channels * width, |len, &n| len.checked_mul(n as usize));
+    if len != Some(data.len()) {
+        let expected = len.map_or("too many".to_string(), |len| len.to_string());
+        let reason = format!(
+            "expected {} bytes of texels for {}x{}x{} with {} channels, got {}",
+            expected,
+            size[0],
+            size[1],
+            size[2],
+            channels,
+            data.len()
+        );
+        return Err(InvalidVolumeError::new(path, reason).into());
+    }
+
+    let mut texels = Vec::with_capacity(data.len() / channels / width * 4);
+    for texel in data.chunks(channels * width) {
+        for channel in 0..4 {
+            texels.push(if channel < channels {
+                parse(&texel[channel * width..(channel + 1) * width])
+            } else if channel == 3 {
+                one
+            } else {
+                T::default()
+            });
+        }
+    }
+    Ok(texels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An image of faces of the given size laid out on a grid, each filled with a color made from
+    // its cell, with a marker in its top-left corner.
+    fn faces_image(columns: u32, rows: u32, size: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(
+            columns * size,
+            rows * size,
+            |x, y| {
+                let marker = x % size == 0 && y % size == 0;
+                image::Rgba([
+                    (x / size) as u8,
+                    (y / size) as u8,
+                    0,
+                    if marker { 0 } else { 255 },
+                ])
+            },
+        ))
+    }
+
+    // Cells the faces were cut from, and whether they were turned upside down.
+    fn cells(faces: &[image::DynamicImage], size: u32) -> Vec<(u8, u8, bool)> {
+        faces
+            .iter()
+            .map(|face| {
+                assert_eq!(face.dimensions(), (size, size));
+                let center = face.get_pixel(size / 2, size / 2).data;
+                let upside_down = face.get_pixel(size - 1, size - 1).data[3] == 0;
+                assert!(upside_down || face.get_pixel(0, 0).data[3] == 0);
+                (center[0], center[1], upside_down)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_horizontal_cross() {
+        let faces = split_cubemap("cross.png", faces_image(4, 3, 4)).unwrap();
+        assert_eq!(
+            cells(&faces, 4),
+            [
+                (2, 1, false),
+                (0, 1, false),
+                (1, 0, false),
+                (1, 2, false),
+                (1, 1, false),
+                (3, 1, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_vertical_cross() {
+        let faces = split_cubemap("cross.png", faces_image(3, 4, 4)).unwrap();
+        assert_eq!(
+            cells(&faces, 4),
+            [
+                (2, 1, false),
+                (0, 1, false),
+                (1, 0, false),
+                (1, 2, false),
+                (1, 1, false),
+                (1, 3, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_strips() {
+        let faces = split_cubemap("strip.png", faces_image(6, 1, 4)).unwrap();
+        let expected: Vec<_> = (0..6).map(|i| (i, 0, false)).collect();
+        assert_eq!(cells(&faces, 4), expected);
+
+        let faces = split_cubemap("strip.png", faces_image(1, 6, 4)).unwrap();
+        let expected: Vec<_> = (0..6).map(|i| (0, i, false)).collect();
+        assert_eq!(cells(&faces, 4), expected);
+    }
+
+    // Writes a .bin volume with the given header fields to a temporary file, returning its path.
+    fn bin_volume(name: &str, size: [u32; 3], channels: u8, format: u16, body: &[u8]) -> String {
+        let mut data = BIN_SIGNATURE.to_vec();
+        for n in size.iter() {
+            data.extend(n.to_le_bytes());
+        }
+        data.extend([channels, 0]);
+        data.extend(format.to_le_bytes());
+        data.extend(body);
+
+        let path =
+            std::env::temp_dir().join(format!("shadertoy_rs_{}_{}.bin", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn bin_volume_error(path: &str) -> String {
+        let result = read_bin_volume(path);
+        fs::remove_file(path).unwrap();
+        match result {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("{} was read", path),
+        }
+    }
+
+    #[test]
+    fn reads_bin_volumes() {
+        let path = bin_volume("unorm", [2, 1, 1], 1, 0, &[10, 20]);
+        let result = read_bin_volume(&path);
+        fs::remove_file(&path).unwrap();
+        match result.unwrap() {
+            ([2, 1, 1], VolumeTexels::Unorm(texels)) => {
+                assert_eq!(texels, [10, 0, 0, 255, 20, 0, 0, 255])
+            }
+            _ => panic!("unexpected size or texel format"),
+        }
+
+        let body: Vec<u8> = [0.5f32, 0.25]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let path = bin_volume("float", [1, 1, 1], 2, 10, &body);
+        let result = read_bin_volume(&path);
+        fs::remove_file(&path).unwrap();
+        match result.unwrap() {
+            ([1, 1, 1], VolumeTexels::Float(texels)) => assert_eq!(texels, [0.5, 0.25, 0.0, 1.0]),
+            _ => panic!("unexpected size or texel format"),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_bin_volumes() {
+        // Shorter than a header.
+        let path = bin_volume("short", [0, 0, 0], 0, 0, &[]);
+        fs::write(&path, b"BIN\0\x02\0").unwrap();
+        assert!(bin_volume_error(&path).contains("too short for a header"));
+
+        // Anything but a .bin volume, even if it's long enough.
+        let path = bin_volume("signature", [1, 1, 1], 1, 0, &[0]);
+        let mut data = fs::read(&path).unwrap();
+        data[..4].copy_from_slice(b"\x89PNG");
+        fs::write(&path, data).unwrap();
+        assert!(bin_volume_error(&path).contains("no BIN signature"));
+        let path = bin_volume("text", [0, 0, 0], 0, 0, &[]);
+        fs::write(&path, "this is not a volume file\n").unwrap();
+        assert!(bin_volume_error(&path).contains("no BIN signature"));
+
+        // Fewer or more texels than the header says.
+        let path = bin_volume("truncated", [2, 2, 2], 4, 0, &[0; 31]);
+        assert!(bin_volume_error(&path).contains("expected 32 bytes"));
+        let path = bin_volume("long", [2, 2, 2], 4, 0, &[0; 33]);
+        assert!(bin_volume_error(&path).contains("expected 32 bytes"));
+
+        // Sizes too big for a texture, or empty.
+        let path = bin_volume("oversized", [u32::MAX; 3], 4, 10, &[0; 16]);
+        assert!(bin_volume_error(&path).contains("can't make a"));
+        let path = bin_volume("empty", [0, 1, 1], 4, 0, &[]);
+        assert!(bin_volume_error(&path).contains("can't make a 0x1x1 texture"));
+
+        let path = bin_volume("channels", [1, 1, 1], 5, 0, &[0; 5]);
+        assert!(bin_volume_error(&path).contains("1 to 4 channels"));
+        let path = bin_volume("format", [1, 1, 1], 1, 3, &[0]);
+        assert!(bin_volume_error(&path).contains("unsupported texel format 3"));
+    }
+
+    #[test]
+    fn expands_texels() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        let expand = |channels| {
+            let size = [8 / u32::from(channels), 1, 1];
+            expand_texels("v.raw", size, channels, &data, |bytes| bytes[0], 255).unwrap()
+        };
+        assert_eq!(
+            expand(1),
+            [
+                1, 0, 0, 255, 2, 0, 0, 255, 3, 0, 0, 255, 4, 0, 0, 255, 5, 0, 0, 255, 6, 0, 0, 255,
+                7, 0, 0, 255, 8, 0, 0, 255
+            ]
+        );
+        assert_eq!(
+            expand(2),
+            [1, 2, 0, 255, 3, 4, 0, 255, 5, 6, 0, 255, 7, 8, 0, 255]
+        );
+        assert_eq!(
+            expand_texels("v.raw", [2, 1, 1], 3, &data[..6], |bytes| bytes[0], 255).unwrap(),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+        assert_eq!(expand(4), data);
+    }
+
+    #[test]
+    fn rejects_other_layouts() {
+        match split_cubemap("square.png", faces_image(2, 2, 4)) {
+            Err(err) => assert!(err.to_string().contains("8x8"), "{}", err),
+            Ok(_) => panic!("an 8x8 image was split into faces"),
+        }
+    }
 }
@@ -5,30 +5,64 @@
 extern crate clap;
 #[macro_use]
 extern crate gfx;
+extern crate gfx_device_gl;
 extern crate gfx_window_glutin;
 extern crate glutin;
+extern crate hound;
 extern crate image;
 #[macro_use]
 extern crate log;
 extern crate anyhow;
+extern crate chrono;
+extern crate dirs;
 extern crate env_logger;
+extern crate exr;
 extern crate notify;
 extern crate old_school_gfx_glutin_ext;
 extern crate reqwest;
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "playback")]
+extern crate rodio;
 extern crate serde_json;
+extern crate sha2;
+extern crate shadertoy_rs;
+extern crate toml;
 
 mod argvalues;
+mod batch;
+mod cache;
+mod capture;
+mod config;
 mod download;
-mod error;
-mod loader;
+mod library;
 mod runner;
+mod schema;
+mod sound;
+
+use shadertoy_rs::{error, headless, keyboard, loader, mouse, overlay, pass, project, renderer};
 
 use argvalues::ArgValues;
 
 fn main() {
     env_logger::init().expect("Unable to initialize logger");
 
-    if let Err(e) = ArgValues::from_cli().and_then(runner::run) {
+    let result = ArgValues::from_cli().and_then(|av| {
+        if let Some(ref soundpath) = av.soundpath {
+            sound::render(&av, soundpath)
+        } else if let Some(ref outpath) = av.render_outpath {
+            capture::render(&av, outpath)
+        } else if let Some(ref outpath) = av.record_outpath {
+            capture::record(&av, outpath)
+        } else if let Some(ref batch) = av.batch {
+            batch::get(&av, batch)
+        } else if let Some(ref terms) = av.search_terms {
+            library::list(terms)
+        } else {
+            runner::run(av)
+        }
+    });
+    if let Err(e) = result {
         error!("{}", e);
     }
 }
@@ -0,0 +1,159 @@
+// Mouse state as shadertoy.com exposes it to shaders through iMouse:
+//
+// - xy is where the cursor was the last time the left button was down. It only moves while the
+//   button is held, and keeps its last position once released.
+// - zw is where the button was last pressed. z is positive while the button is held and negated
+//   once released, and w is positive only on the frame the button was pressed.
+//
+// Older shaders may expect the convention from before the click signal in w was added, where z and
+// w have the same sign: positive while the button is held, negative once released.
+
+// Which iMouse convention to follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseMode {
+    Modern,
+    Legacy,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mouse {
+    mode: MouseMode,
+
+    // Last known position of the cursor, whether or not the button is down.
+    cursor: [f32; 2],
+
+    // iMouse.xy and iMouse.zw, before any signs are applied.
+    position: [f32; 2],
+    click: [f32; 2],
+
+    down: bool,
+
+    // True if the button was pressed since the last frame.
+    clicked: bool,
+}
+
+impl Mouse {
+    pub fn new(mode: MouseMode) -> Mouse {
+        Mouse {
+            mode,
+            cursor: [0.0; 2],
+            position: [0.0; 2],
+            click: [0.0; 2],
+            down: false,
+            clicked: false,
+        }
+    }
+
+    // Moves the cursor to a position in pixels, with (0, 0) at the bottom left as in fragCoord.
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.cursor = [x, y];
+        if self.down {
+            self.position = self.cursor;
+        }
+    }
+
+    pub fn press(&mut self) {
+        if !self.down {
+            self.down = true;
+            self.clicked = true;
+            self.position = self.cursor;
+            self.click = self.cursor;
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.down = false;
+    }
+
+    // Value of iMouse for the next frame.
+    pub fn uniform(&self) -> [f32; 4] {
+        let [x, y] = self.position;
+        let [click_x, click_y] = self.click;
+        let sign = |positive: bool| if positive { 1.0 } else { -1.0 };
+        match self.mode {
+            MouseMode::Modern => [
+                x,
+                y,
+                sign(self.down) * click_x,
+                sign(self.clicked) * click_y,
+            ],
+            MouseMode::Legacy => [x, y, sign(self.down) * click_x, sign(self.down) * click_y],
+        }
+    }
+
+    // Called after each frame, so a click is only signaled on the frame it happened before.
+    pub fn end_frame(&mut self) {
+        self.clicked = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // iMouse across a click and drag from (10, 20) to (30, 40), returning its value after the
+    // press, the move, the end of the frame, the release and another move.
+    fn drag(mode: MouseMode) -> [[f32; 4]; 5] {
+        let mut mouse = Mouse::new(mode);
+        mouse.move_to(10.0, 20.0);
+        mouse.press();
+        let pressed = mouse.uniform();
+        mouse.move_to(30.0, 40.0);
+        let moved = mouse.uniform();
+        mouse.end_frame();
+        let held = mouse.uniform();
+        mouse.release();
+        let released = mouse.uniform();
+        mouse.move_to(50.0, 60.0);
+        [pressed, moved, held, released, mouse.uniform()]
+    }
+
+    #[test]
+    fn follows_modern_convention() {
+        assert_eq!(
+            drag(MouseMode::Modern),
+            [
+                [10.0, 20.0, 10.0, 20.0],
+                [30.0, 40.0, 10.0, 20.0],
+                [30.0, 40.0, 10.0, -20.0],
+                [30.0, 40.0, -10.0, -20.0],
+                [30.0, 40.0, -10.0, -20.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_legacy_convention() {
+        assert_eq!(
+            drag(MouseMode::Legacy),
+            [
+                [10.0, 20.0, 10.0, 20.0],
+                [30.0, 40.0, 10.0, 20.0],
+                [30.0, 40.0, 10.0, 20.0],
+                [30.0, 40.0, -10.0, -20.0],
+                [30.0, 40.0, -10.0, -20.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_at_origin() {
+        for &mode in [MouseMode::Modern, MouseMode::Legacy].iter() {
+            let mut mouse = Mouse::new(mode);
+            mouse.move_to(5.0, 5.0);
+            assert_eq!(mouse.uniform(), [0.0; 4]);
+        }
+    }
+
+    #[test]
+    fn signals_clicks_released_before_the_frame() {
+        // A click that's over by the next frame still signals w for that frame.
+        let mut mouse = Mouse::new(MouseMode::Modern);
+        mouse.move_to(10.0, 20.0);
+        mouse.press();
+        mouse.release();
+        assert_eq!(mouse.uniform(), [10.0, 20.0, -10.0, 20.0]);
+        mouse.end_frame();
+        assert_eq!(mouse.uniform(), [10.0, 20.0, -10.0, -20.0]);
+    }
+}
@@ -0,0 +1,270 @@
+// Text drawn over the window, used to show why a hot reload failed on top of the previous shader
+// (dimmed) until the next successful reload.
+
+use error;
+use gfx;
+use gfx::texture;
+use gfx::traits::FactoryExt;
+use loader;
+use renderer::{ColorFormat, Vertex, SCREEN, SCREEN_INDICES};
+
+use image;
+
+// Glyphs of ASCII 32-126 from DejaVu Sans Mono, 16 per row, in cells of 8x16 pixels.
+static FONT_BUF: &[u8] = include_bytes!("../textures/font.png");
+
+static OVERLAY_FRAG_SRC_BUF: &[u8] = include_bytes!("../shaders/overlay.frag");
+
+// Longest line and most lines shown. Anything past them wouldn't fit in the window anyway.
+const MAX_COLUMNS: usize = 256;
+const MAX_LINES: usize = 256;
+
+gfx_defines! {
+    pipeline overlay_pipe {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        i_resolution: gfx::Global<[f32; 3]> = "iResolution",
+        font: gfx::TextureSampler<f32> = "tFont",
+        text: gfx::TextureSampler<[f32; 4]> = "tText",
+        scale: gfx::Global<f32> = "uScale",
+        out: gfx::BlendTarget<ColorFormat> = (
+            "fragColor",
+            gfx::state::ColorMask::all(),
+            gfx::preset::blend::ALPHA,
+        ),
+    }
+}
+
+// How a character is colored, matching STYLES in overlay.frag.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Style {
+    Text = 0,
+    Error = 1,
+    Warning = 2,
+    Location = 3,
+}
+
+pub struct Overlay<R: gfx::Resources> {
+    pso: gfx::PipelineState<R, overlay_pipe::Meta>,
+    data: overlay_pipe::Data<R>,
+    slice: gfx::Slice<R>,
+
+    // True while there's a message to draw.
+    visible: bool,
+}
+
+impl<R: gfx::Resources> Overlay<R> {
+    pub fn new<F>(
+        factory: &mut F,
+        target: &gfx::handle::RenderTargetView<R, ColorFormat>,
+    ) -> error::Result<Overlay<R>>
+    where
+        F: gfx::Factory<R>,
+    {
+        let pso = factory.create_pipeline_simple(
+            &loader::load_vertex_shader(),
+            OVERLAY_FRAG_SRC_BUF,
+            overlay_pipe::new(),
+        )?;
+        let (vertex_buffer, slice) =
+            factory.create_vertex_buffer_with_slice(&SCREEN, &SCREEN_INDICES[..]);
+
+        let font = image::load_from_memory(FONT_BUF)?.to_luma();
+        let (w, h) = font.dimensions();
+        let kind = texture::Kind::D2(w as u16, h as u16, texture::AaMode::Single);
+        let (_, font_view) = factory
+            .create_texture_immutable_u8::<(gfx::format::R8, gfx::format::Unorm)>(
+                kind,
+                texture::Mipmap::Provided,
+                &[&font],
+            )?;
+
+        // Texels are fetched directly, so filtering doesn't matter.
+        let sampler = factory.create_sampler(texture::SamplerInfo::new(
+            texture::FilterMethod::Scale,
+            texture::WrapMode::Clamp,
+        ));
+        let text_view = create_text(factory, &[])?;
+
+        let data = overlay_pipe::Data {
+            vbuf: vertex_buffer,
+            i_resolution: [0.0; 3],
+            font: (font_view, sampler.clone()),
+            text: (text_view, sampler),
+            scale: 1.0,
+            out: target.clone(),
+        };
+
+        Ok(Overlay {
+            pso,
+            data,
+            slice,
+            visible: false,
+        })
+    }
+
+    // Shows a message until hidden or replaced. Non-ASCII characters are shown as '?'.
+    pub fn show<F>(&mut self, factory: &mut F, message: &str) -> error::Result<()>
+    where
+        F: gfx::Factory<R>,
+    {
+        let lines: Vec<&str> = message.lines().take(MAX_LINES).collect();
+        self.data.text.0 = create_text(factory, &lines)?;
+        self.visible = true;
+        Ok(())
+    }
+
+    pub fn hide(&mut self) {
+        self.visible = false;
+    }
+
+    // Draws the message (if there is one) over whatever is in the target. Text is scaled by the
+    // window's scale factor, rounded to a whole number so glyphs stay sharp.
+    pub fn render<C>(
+        &mut self,
+        encoder: &mut gfx::Encoder<R, C>,
+        target: &gfx::handle::RenderTargetView<R, ColorFormat>,
+        scale_factor: f64,
+    ) where
+        C: gfx::CommandBuffer<R>,
+    {
+        if !self.visible {
+            return;
+        }
+        let (width, height, _, _) = target.get_dimensions();
+        self.data.i_resolution = [width as f32, height as f32, 1.0];
+        self.data.scale = scale_factor.round().max(1.0) as f32;
+        self.data.out = target.clone();
+        encoder.draw(&self.slice, &self.pso, &self.data);
+    }
+}
+
+// Creates a texture with a texel per character of the lines (in rows from the top), holding the
+// character's code and style.
+fn create_text<F, R>(
+    factory: &mut F,
+    lines: &[&str],
+) -> error::Result<gfx::handle::ShaderResourceView<R, [f32; 4]>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    let styled: Vec<Vec<(u8, Style)>> = lines.iter().map(|line| style_line(line)).collect();
+    let columns = styled
+        .iter()
+        .map(Vec::len)
+        .max()
+        .unwrap_or(0)
+        .clamp(1, MAX_COLUMNS);
+    let rows = styled.len().max(1);
+
+    let mut texels = vec![0; columns * rows * 4];
+    for (row, line) in styled.iter().enumerate() {
+        for (column, &(code, style)) in line.iter().take(columns).enumerate() {
+            let i = (row * columns + column) * 4;
+            texels[i] = code;
+            texels[i + 1] = style as u8;
+        }
+    }
+
+    let kind = texture::Kind::D2(columns as u16, rows as u16, texture::AaMode::Single);
+    let (_, view) = factory.create_texture_immutable_u8::<gfx::format::Rgba8>(
+        kind,
+        texture::Mipmap::Provided,
+        &[&texels],
+    )?;
+    Ok(view)
+}
+
+// Colors a line like rustc colors its diagnostics: severities, and the gutter and location lines
+// of snippets, with carets in red. Positions are found in the text as it's drawn, after tabs are
+// expanded and non-ASCII characters replaced, so they line up with the cells they color.
+fn style_line(line: &str) -> Vec<(u8, Style)> {
+    let text: String = line
+        .chars()
+        .flat_map(|c| match c {
+            '\t' => vec![' '; 4],
+            ' '..='~' => vec![c],
+            _ => vec!['?'],
+        })
+        .collect();
+
+    let indent = text.len() - text.trim_start().len();
+    let trimmed = &text[indent..];
+    let gutter = trimmed
+        .find('|')
+        .filter(|&end| trimmed[..end].trim().chars().all(|c| c.is_ascii_digit()))
+        .map(|end| indent + end + 1);
+
+    let mut styles = vec![Style::Text; text.len()];
+    if trimmed.starts_with("error") {
+        let end = indent + trimmed.find(':').unwrap_or(0);
+        fill(&mut styles, 0, end, Style::Error);
+    } else if trimmed.starts_with("warning") {
+        let end = indent + trimmed.find(':').unwrap_or(0);
+        fill(&mut styles, 0, end, Style::Warning);
+    } else if trimmed.starts_with("-->") {
+        fill(&mut styles, 0, indent + 3, Style::Location);
+    } else if let Some(gutter) = gutter {
+        fill(&mut styles, 0, gutter, Style::Location);
+
+        // The line under a snippet's code, pointing at the problem.
+        let rest = text[gutter..].trim();
+        if !rest.is_empty() && rest.chars().all(|c| c == '^') {
+            fill(&mut styles, gutter, text.len(), Style::Error);
+        }
+    }
+    text.bytes().zip(styles).collect()
+}
+
+fn fill(styles: &mut [Style], start: usize, end: usize, style: Style) {
+    let end = end.min(styles.len());
+    for s in &mut styles[start.min(end)..end] {
+        *s = style;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styles(line: &str) -> Vec<Style> {
+        style_line(line)
+            .into_iter()
+            .map(|(_, style)| style)
+            .collect()
+    }
+
+    #[test]
+    fn colors_carets_after_tabs() {
+        // The gutter ends at cell 3, and the tab is drawn as four cells.
+        let styled = style_line("  | \t    ^");
+        assert_eq!(styled.len(), 13);
+        assert!(styled[..3]
+            .iter()
+            .all(|&(_, style)| style == Style::Location));
+        assert!(styled[3..].iter().all(|&(_, style)| style == Style::Error));
+    }
+
+    #[test]
+    fn colors_gutters_after_multibyte_characters() {
+        let styled = styles("é2 | x");
+        assert_eq!(styled.len(), 6);
+        assert_eq!(styled[..4], [Style::Text; 4]);
+        assert_eq!(styled[4..], [Style::Text; 2]);
+
+        let styled = style_line("\t2 | é^");
+        assert_eq!(styled.len(), 10);
+        assert!(styled[..7]
+            .iter()
+            .all(|&(_, style)| style == Style::Location));
+        assert_eq!(styled[8].0, b'?');
+    }
+
+    #[test]
+    fn colors_severities() {
+        let styled = styles("error: oops");
+        assert_eq!(styled[..5], [Style::Error; 5]);
+        assert_eq!(styled[5..], [Style::Text; 6]);
+        assert_eq!(styles(" --> a.glsl:2")[..4], [Style::Location; 4]);
+    }
+}
@@ -0,0 +1,347 @@
+use error::{self, InvalidChannelBindingError};
+use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+
+// Identifies one of the four offscreen buffer passes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferId {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl BufferId {
+    pub const ALL: [BufferId; 4] = [BufferId::A, BufferId::B, BufferId::C, BufferId::D];
+
+    pub fn index(self) -> usize {
+        match self {
+            BufferId::A => 0,
+            BufferId::B => 1,
+            BufferId::C => 2,
+            BufferId::D => 3,
+        }
+    }
+
+    // Name used on the command line, e.g. "buffer_a".
+    pub fn arg_name(self) -> &'static str {
+        match self {
+            BufferId::A => "buffer_a",
+            BufferId::B => "buffer_b",
+            BufferId::C => "buffer_c",
+            BufferId::D => "buffer_d",
+        }
+    }
+
+    // Name used by shadertoy.com, e.g. "Buffer A".
+    pub fn name(self) -> &'static str {
+        match self {
+            BufferId::A => "Buffer A",
+            BufferId::B => "Buffer B",
+            BufferId::C => "Buffer C",
+            BufferId::D => "Buffer D",
+        }
+    }
+
+    pub fn from_arg_name(name: &str) -> Option<BufferId> {
+        BufferId::ALL
+            .iter()
+            .cloned()
+            .find(|id| id.arg_name() == name)
+    }
+}
+
+// A single render pass. Buffers are rendered in order A-D, followed by the Cube A pass and then
+// the image pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassId {
+    Buffer(BufferId),
+
+    // Shadertoy's "Cube A" tab, rendering each face of a cubemap with mainCubemap.
+    CubeA,
+
+    Image,
+}
+
+impl PassId {
+    pub fn name(self) -> &'static str {
+        match self {
+            PassId::Buffer(id) => id.name(),
+            PassId::CubeA => "Cube A",
+            PassId::Image => "Image",
+        }
+    }
+
+    fn from_arg_name(name: &str) -> Option<PassId> {
+        match name {
+            "image" => Some(PassId::Image),
+            "cube_a" => Some(PassId::CubeA),
+            _ => BufferId::from_arg_name(name).map(PassId::Buffer),
+        }
+    }
+}
+
+// What a pass samples from through one of its iChannelN uniforms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelInput {
+    // The n-th texture from the command line (or the n-th default texture).
+    Texture(usize),
+
+    // Output of a buffer pass. A pass reading a buffer that renders before it sees this frame's
+    // output; otherwise (including a buffer reading itself) it sees the previous frame's output.
+    Buffer(BufferId),
+
+    // Output of the Cube A pass, as a cubemap. As with buffers, the image pass sees this frame's
+    // output, and the buffers and the Cube A pass itself see the previous frame's.
+    CubeA,
+
+    // Keyboard state, as a 256x3 texture (see keyboard.rs).
+    Keyboard,
+
+    // Nothing. Reads as transparent black, with a resolution of zero.
+    Empty,
+}
+
+impl ChannelInput {
+    fn from_arg_name(name: &str) -> Option<ChannelInput> {
+        match name {
+            "texture0" => Some(ChannelInput::Texture(0)),
+            "texture1" => Some(ChannelInput::Texture(1)),
+            "texture2" => Some(ChannelInput::Texture(2)),
+            "texture3" => Some(ChannelInput::Texture(3)),
+            "keyboard" => Some(ChannelInput::Keyboard),
+            _ => ChannelInput::from_pass_name(name),
+        }
+    }
+
+    // Output of the pass named as on the command line: a buffer (e.g. "buffer_a") or "cube_a".
+    pub fn from_pass_name(name: &str) -> Option<ChannelInput> {
+        match name {
+            "cube_a" => Some(ChannelInput::CubeA),
+            _ => BufferId::from_arg_name(name).map(ChannelInput::Buffer),
+        }
+    }
+
+    // Sampler the input needs regardless of the channel's settings, if any. The keyboard is
+    // looked up texel by texel, so it's never filtered.
+    fn sampler(self) -> Option<SamplerInfo> {
+        match self {
+            ChannelInput::Keyboard => Some(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp)),
+            _ => None,
+        }
+    }
+}
+
+// Binds a channel of a pass to an input, overriding the default of iChannelN reading textureN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelBinding {
+    pub pass: PassId,
+    pub channel: usize,
+    pub input: ChannelInput,
+
+    // Overrides the channel's default sampler in this pass. None to keep the default.
+    pub sampler: Option<SamplerInfo>,
+}
+
+impl ChannelBinding {
+    // Parses a binding of the form "<pass>:<channel>=<input>", e.g. "image:0=buffer_a".
+    pub fn parse(binding: &str) -> error::Result<ChannelBinding> {
+        let invalid = || InvalidChannelBindingError::new(binding);
+
+        let (target, input) = binding.split_once('=').ok_or_else(invalid)?;
+        let (pass, channel) = target.split_once(':').ok_or_else(invalid)?;
+
+        let pass = PassId::from_arg_name(pass).ok_or_else(invalid)?;
+        let channel = match channel.parse::<usize>() {
+            Ok(channel) if channel < 4 => channel,
+            _ => return Err(invalid().into()),
+        };
+        let input = ChannelInput::from_arg_name(input).ok_or_else(invalid)?;
+
+        Ok(ChannelBinding {
+            pass,
+            channel,
+            input,
+            sampler: input.sampler(),
+        })
+    }
+}
+
+fn find_binding(
+    bindings: &[ChannelBinding],
+    pass: PassId,
+    channel: usize,
+) -> Option<&ChannelBinding> {
+    bindings
+        .iter()
+        .rev()
+        .find(|binding| binding.pass == pass && binding.channel == channel)
+}
+
+// Returns the input for the given channel of a pass, taking the last matching binding.
+pub fn channel_input(bindings: &[ChannelBinding], pass: PassId, channel: usize) -> ChannelInput {
+    find_binding(bindings, pass, channel)
+        .map(|binding| binding.input)
+        .unwrap_or(ChannelInput::Texture(channel))
+}
+
+// Returns the sampler for the given channel of a pass, falling back to the channel's default.
+// Buffers and Cube A have no mipmaps, so filters that use them are swapped for the closest ones
+// that don't when a channel reads a pass.
+pub fn channel_sampler(
+    bindings: &[ChannelBinding],
+    pass: PassId,
+    channel: usize,
+    default: SamplerInfo,
+) -> SamplerInfo {
+    let mut sampler = find_binding(bindings, pass, channel)
+        .and_then(|binding| binding.sampler)
+        .unwrap_or(default);
+    if let ChannelInput::Buffer(_) | ChannelInput::CubeA = channel_input(bindings, pass, channel) {
+        sampler.filter = match sampler.filter {
+            FilterMethod::Mipmap => FilterMethod::Scale,
+            FilterMethod::Trilinear | FilterMethod::Anisotropic(_) => FilterMethod::Bilinear,
+            filter => filter,
+        };
+    }
+    sampler
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(pass: PassId, channel: usize, input: ChannelInput) -> ChannelBinding {
+        ChannelBinding {
+            pass,
+            channel,
+            input,
+            sampler: input.sampler(),
+        }
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let parse = |binding: &str| ChannelBinding::parse(binding).unwrap();
+        assert_eq!(
+            parse("image:0=buffer_a"),
+            binding(PassId::Image, 0, ChannelInput::Buffer(BufferId::A))
+        );
+        assert_eq!(
+            parse("buffer_d:3=texture1"),
+            binding(PassId::Buffer(BufferId::D), 3, ChannelInput::Texture(1))
+        );
+        assert_eq!(
+            parse("cube_a:1=cube_a"),
+            binding(PassId::CubeA, 1, ChannelInput::CubeA)
+        );
+
+        let keyboard = parse("buffer_b:2=keyboard");
+        assert_eq!(keyboard.input, ChannelInput::Keyboard);
+        assert_eq!(
+            keyboard.sampler,
+            Some(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_bindings() {
+        let bindings = [
+            "",
+            "image",
+            "image:0",
+            "image=buffer_a",
+            "image:=buffer_a",
+            "image:4=buffer_a",
+            "image:-1=buffer_a",
+            "image:x=buffer_a",
+            "image:0=",
+            "image:0=buffer_e",
+            "image:0=texture4",
+            "buffer_e:0=buffer_a",
+            "Image:0=buffer_a",
+            " image:0=buffer_a",
+            "image:0=buffer_a=buffer_b",
+        ];
+        for &binding in bindings.iter() {
+            match ChannelBinding::parse(binding) {
+                Err(err) => assert!(
+                    err.to_string()
+                        .starts_with(&format!("Invalid channel binding {} ", binding)),
+                    "{}",
+                    err
+                ),
+                Ok(parsed) => panic!("{:?} parsed as {:?}", binding, parsed),
+            }
+        }
+    }
+
+    #[test]
+    fn takes_last_binding() {
+        let bindings = [
+            binding(PassId::Image, 0, ChannelInput::Buffer(BufferId::A)),
+            binding(PassId::Image, 0, ChannelInput::Keyboard),
+        ];
+        assert_eq!(
+            channel_input(&bindings, PassId::Image, 0),
+            ChannelInput::Keyboard
+        );
+        assert_eq!(
+            channel_input(&bindings, PassId::Image, 1),
+            ChannelInput::Texture(1)
+        );
+        assert_eq!(
+            channel_input(&bindings, PassId::Buffer(BufferId::A), 0),
+            ChannelInput::Texture(0)
+        );
+    }
+
+    #[test]
+    fn samples_passes_without_mipmaps() {
+        let sampler = |input, filter| {
+            let mut binding = binding(PassId::Image, 0, input);
+            binding.sampler = Some(SamplerInfo::new(filter, WrapMode::Clamp));
+            channel_sampler(
+                &[binding],
+                PassId::Image,
+                0,
+                SamplerInfo::new(filter, WrapMode::Tile),
+            )
+            .filter
+        };
+        let buffer = ChannelInput::Buffer(BufferId::B);
+        assert_eq!(
+            sampler(buffer, FilterMethod::Trilinear),
+            FilterMethod::Bilinear
+        );
+        assert_eq!(
+            sampler(buffer, FilterMethod::Anisotropic(8)),
+            FilterMethod::Bilinear
+        );
+        assert_eq!(sampler(buffer, FilterMethod::Mipmap), FilterMethod::Scale);
+        assert_eq!(
+            sampler(buffer, FilterMethod::Bilinear),
+            FilterMethod::Bilinear
+        );
+        assert_eq!(
+            sampler(ChannelInput::CubeA, FilterMethod::Trilinear),
+            FilterMethod::Bilinear
+        );
+
+        let texture = ChannelInput::Texture(0);
+        assert_eq!(
+            sampler(texture, FilterMethod::Trilinear),
+            FilterMethod::Trilinear
+        );
+        assert_eq!(sampler(texture, FilterMethod::Mipmap), FilterMethod::Mipmap);
+
+        // The channel's default sampler also applies to passes bound without one.
+        let bindings = [binding(PassId::Image, 0, buffer)];
+        let default = SamplerInfo::new(FilterMethod::Trilinear, WrapMode::Tile);
+        let sampler = channel_sampler(&bindings, PassId::Image, 0, default);
+        assert_eq!(sampler.filter, FilterMethod::Bilinear);
+        assert_eq!(
+            sampler.wrap_mode,
+            (WrapMode::Tile, WrapMode::Tile, WrapMode::Tile)
+        );
+    }
+}
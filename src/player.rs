@@ -0,0 +1,63 @@
+use error;
+use gfx::format::{Formatted, Rgba8};
+use gfx::memory::Typed;
+use gfx::texture;
+use headless::{Encoder, Headless, ReadbackTarget, Resources};
+use renderer::{Renderer, ShaderConfig};
+
+// Plays a shader offscreen, with no window or display needed. Uniforms are set through the
+// underlying Renderer before each call to render.
+pub struct ShaderPlayer {
+    renderer: Renderer<Resources>,
+    target: ReadbackTarget<Rgba8>,
+    encoder: Encoder,
+
+    // Declared last so the GL context outlives everything above.
+    headless: Headless,
+}
+
+impl ShaderPlayer {
+    pub fn new(
+        config: &ShaderConfig,
+        width: texture::Size,
+        height: texture::Size,
+    ) -> error::Result<ShaderPlayer> {
+        let mut headless = Headless::new()?;
+        let mut encoder = headless.create_encoder();
+        let target = headless.create_readback_target::<Rgba8>(width, height)?;
+        let renderer = Renderer::new(
+            &mut headless.factory,
+            &mut encoder,
+            config,
+            Rgba8::get_format(),
+            f32::from(width),
+            f32::from(height),
+            target.target.raw(),
+        )?;
+
+        Ok(ShaderPlayer {
+            renderer,
+            target,
+            encoder,
+            headless,
+        })
+    }
+
+    pub fn renderer(&mut self) -> &mut Renderer<Resources> {
+        &mut self.renderer
+    }
+
+    // Renders the next frame, returning its RGBA pixels with the top row first.
+    pub fn render(&mut self) -> error::Result<Vec<[u8; 4]>> {
+        self.renderer
+            .render(&mut self.encoder, self.target.target.raw());
+        let mut pixels: Vec<[u8; 4]> =
+            self.headless.read_pixels(&mut self.encoder, &self.target)?;
+
+        // Rows are read back bottom first.
+        let width = usize::from(self.target.texture.get_info().kind.get_dimensions().0);
+        let rows: Vec<&[[u8; 4]]> = pixels.chunks(width).rev().collect();
+        pixels = rows.concat();
+        Ok(pixels)
+    }
+}
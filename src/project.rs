@@ -0,0 +1,1019 @@
+// Project manifests describe a complete shader setup in a single TOML or JSON file: the source of
+// each pass, what each of their channels reads, sampler settings, resolution and title. Paths are
+// relative to the directory containing the manifest. For example:
+//
+//     title = "Fluid"
+//     width = 800
+//     height = 450
+//     common = "common.glsl"
+//
+//     [image]
+//     source = "image.glsl"
+//     channel0 = { type = "buffer", buffer = "buffer_a" }
+//
+//     [buffer_a]
+//     source = "buffer_a.glsl"
+//     channel0 = { type = "buffer", buffer = "buffer_a", filter = "nearest" }
+//     channel1 = { type = "texture", path = "noise.png", wrap = "repeat" }
+//
+// Channels that aren't listed read nothing.
+
+use error::{self, InvalidManifestError, LoadShaderError, SaveShaderError};
+use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+use loader;
+use pass::{BufferId, ChannelBinding, ChannelInput, PassId};
+use renderer::{ShaderConfig, TextureSource, VolumeSource};
+
+use serde_json;
+use toml;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    // Window title. Defaults to the manifest's path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    // Window (and render) size, unless overridden on the command line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+
+    // Code shared by all passes (Shadertoy's "Common" tab).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub common: Option<String>,
+
+    pub image: PassManifest,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_a: Option<PassManifest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_b: Option<PassManifest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_c: Option<PassManifest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_d: Option<PassManifest>,
+
+    // Pass rendering a cubemap (Shadertoy's "Cube A" tab), read by other passes as "cube_a".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cube_a: Option<PassManifest>,
+
+    // Pass defining mainSound, rendered by the sound subcommand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound: Option<PassManifest>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassManifest {
+    // Path to the pass's shader.
+    pub source: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel0: Option<ChannelManifest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel1: Option<ChannelManifest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel2: Option<ChannelManifest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel3: Option<ChannelManifest>,
+}
+
+// What a channel reads, tagged by "type".
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ChannelManifest {
+    // A 2D texture loaded from an image file. Textures of downloaded shaders may only have the
+    // url they were loaded from on shadertoy.com; until they're downloaded, the channel reads
+    // nothing.
+    Texture {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default = "Filter::texture_default")]
+        filter: Filter,
+        #[serde(default = "Wrap::texture_default")]
+        wrap: Wrap,
+        #[serde(default = "default_vflip")]
+        vflip: bool,
+        #[serde(default)]
+        srgb: bool,
+    },
+
+    // Output of a buffer or Cube A pass, named as on the command line (e.g. "buffer_a" or
+    // "cube_a").
+    Buffer {
+        buffer: String,
+        #[serde(default = "Filter::buffer_default")]
+        filter: Filter,
+        #[serde(default = "Wrap::buffer_default")]
+        wrap: Wrap,
+    },
+
+    // A cubemap. `faces` lists its six faces (+X, -X, +Y, -Y, +Z, -Z); otherwise `path` is either
+    // the first face, if the other five are named after it with suffixes _1 to _5 as on
+    // shadertoy.com, or a single image with the faces laid out as a cross or strip.
+    Cubemap {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        faces: Vec<String>,
+        #[serde(default = "Filter::texture_default")]
+        filter: Filter,
+        #[serde(default = "Wrap::cubemap_default")]
+        wrap: Wrap,
+        #[serde(default)]
+        vflip: bool,
+        #[serde(default)]
+        srgb: bool,
+    },
+
+    // A 3D texture. `slices` lists images of its slices, from front to back; otherwise `path` is
+    // either a .bin file in shadertoy.com's volume format, or 8-bit texels of the given `size` with
+    // `channels` channels each (1 by default), or an image of `depth` slices stacked from top to
+    // bottom.
+    Volume {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        slices: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<[u32; 3]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channels: Option<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        depth: Option<u32>,
+        #[serde(default = "Filter::texture_default")]
+        filter: Filter,
+        #[serde(default = "Wrap::texture_default")]
+        wrap: Wrap,
+    },
+
+    // Keyboard state: which keys are down, were pressed this frame, or are toggled on.
+    Keyboard,
+
+    // Music from an audio file (or shadertoy.com url). Not supported yet, so the channel reads
+    // nothing.
+    Audio {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+}
+
+impl ChannelManifest {
+    // The input's "type", e.g. "texture".
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            ChannelManifest::Texture { .. } => "texture",
+            ChannelManifest::Buffer { .. } => "buffer",
+            ChannelManifest::Cubemap { .. } => "cubemap",
+            ChannelManifest::Volume { .. } => "volume",
+            ChannelManifest::Keyboard => "keyboard",
+            ChannelManifest::Audio { .. } => "audio",
+        }
+    }
+
+    // The local path and shadertoy.com url of the file the input reads, if it reads one.
+    pub fn file_mut(&mut self) -> Option<(&mut Option<String>, &mut Option<String>)> {
+        match *self {
+            ChannelManifest::Texture {
+                ref mut path,
+                ref mut url,
+                ..
+            }
+            | ChannelManifest::Cubemap {
+                ref mut path,
+                ref mut url,
+                ..
+            }
+            | ChannelManifest::Volume {
+                ref mut path,
+                ref mut url,
+                ..
+            }
+            | ChannelManifest::Audio {
+                ref mut path,
+                ref mut url,
+            } => Some((path, url)),
+            ChannelManifest::Buffer { .. } | ChannelManifest::Keyboard => None,
+        }
+    }
+}
+
+// Images are flipped to appear upright unless asked not to, as on shadertoy.com.
+fn default_vflip() -> bool {
+    true
+}
+
+// Texture filtering, named as on shadertoy.com.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Nearest,
+    Linear,
+    Mipmap,
+}
+
+impl Filter {
+    fn texture_default() -> Filter {
+        Filter::Mipmap
+    }
+
+    fn buffer_default() -> Filter {
+        Filter::Linear
+    }
+
+    fn method(self) -> FilterMethod {
+        match self {
+            Filter::Nearest => FilterMethod::Scale,
+            Filter::Linear => FilterMethod::Bilinear,
+            Filter::Mipmap => FilterMethod::Trilinear,
+        }
+    }
+}
+
+// Texture wrapping, named as on shadertoy.com.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl Wrap {
+    fn texture_default() -> Wrap {
+        Wrap::Repeat
+    }
+
+    fn buffer_default() -> Wrap {
+        Wrap::Clamp
+    }
+
+    fn cubemap_default() -> Wrap {
+        Wrap::Clamp
+    }
+
+    fn mode(self) -> WrapMode {
+        match self {
+            Wrap::Clamp => WrapMode::Clamp,
+            Wrap::Repeat => WrapMode::Tile,
+            Wrap::Mirror => WrapMode::Mirror,
+        }
+    }
+}
+
+// True if the path looks like a manifest (.toml or .json) rather than a shader.
+pub fn is_manifest(path: &str) -> bool {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    matches!(extension.as_deref(), Some("toml") | Some("json"))
+}
+
+impl Manifest {
+    // The channels of every pass that reads something.
+    pub fn channels_mut(&mut self) -> Vec<&mut ChannelManifest> {
+        let passes = Some(&mut self.image)
+            .into_iter()
+            .chain(self.buffer_a.as_mut())
+            .chain(self.buffer_b.as_mut())
+            .chain(self.buffer_c.as_mut())
+            .chain(self.buffer_d.as_mut())
+            .chain(self.cube_a.as_mut())
+            .chain(self.sound.as_mut());
+        passes
+            .flat_map(|pass| {
+                vec![
+                    pass.channel0.as_mut(),
+                    pass.channel1.as_mut(),
+                    pass.channel2.as_mut(),
+                    pass.channel3.as_mut(),
+                ]
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn passes(&self) -> Vec<(PassId, &PassManifest)> {
+        let buffers = [
+            &self.buffer_a,
+            &self.buffer_b,
+            &self.buffer_c,
+            &self.buffer_d,
+        ];
+        BufferId::ALL
+            .iter()
+            .zip(buffers.iter())
+            .filter_map(|(&id, pass)| pass.as_ref().map(|pass| (PassId::Buffer(id), pass)))
+            .chain(self.cube_a.as_ref().map(|pass| (PassId::CubeA, pass)))
+            .chain(Some((PassId::Image, &self.image)))
+            .collect()
+    }
+}
+
+// A manifest along with where it was loaded from.
+#[derive(Clone, Debug)]
+pub struct Project {
+    pub manifest: Manifest,
+    pub path: PathBuf,
+}
+
+impl Project {
+    // Loads a manifest, parsing it as JSON or TOML depending on the file extension.
+    pub fn load(path: &str) -> error::Result<Project> {
+        let contents = fs::read_to_string(path).map_err(|err| LoadShaderError::new(path, err))?;
+        let invalid = |err: String| InvalidManifestError::new(path, err);
+        let manifest = if path.to_lowercase().ends_with(".json") {
+            serde_json::from_str(&contents).map_err(|err| invalid(err.to_string()))?
+        } else {
+            toml::from_str(&contents).map_err(|err| invalid(err.to_string()))?
+        };
+        Ok(Project {
+            manifest,
+            path: PathBuf::from(path),
+        })
+    }
+
+    // Writes the manifest to its path, as JSON or TOML depending on the file extension.
+    pub fn save(&self) -> error::Result<()> {
+        let contents = if self
+            .path
+            .to_string_lossy()
+            .to_lowercase()
+            .ends_with(".json")
+        {
+            serde_json::to_string_pretty(&self.manifest)?
+        } else {
+            toml::to_string_pretty(&self.manifest)?
+        };
+        fs::write(&self.path, contents)
+            .map_err(|err| SaveShaderError::new(&self.path.to_string_lossy(), err))?;
+        Ok(())
+    }
+
+    // Directory that paths in the manifest are relative to.
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new(""))
+    }
+
+    // Paths of all shader sources, resolved relative to the manifest's directory.
+    pub fn source_paths(&self) -> Vec<PathBuf> {
+        let manifest = &self.manifest;
+        manifest
+            .common
+            .iter()
+            .chain(manifest.passes().iter().map(|&(_, pass)| &pass.source))
+            .map(|source| self.dir().join(source))
+            .collect()
+    }
+
+    // Path of one of the manifest's sources, as named in error messages.
+    pub fn source_path(&self, source: &str) -> String {
+        self.dir().join(source).to_string_lossy().into_owned()
+    }
+
+    // Reads the code of the sound pass and the common code, for rendering sound.
+    pub fn sound_srcs(&self) -> error::Result<(String, String)> {
+        let read = |source: &str| {
+            let path = self.dir().join(source);
+            loader::read_shader_file(&path.to_string_lossy())
+        };
+        let sound = self.manifest.sound.as_ref().ok_or_else(|| {
+            InvalidManifestError::new(&self.path.to_string_lossy(), "no sound pass".to_string())
+        })?;
+        let common_src = match self.manifest.common {
+            Some(ref common) => read(common)?,
+            None => String::new(),
+        };
+        Ok((common_src, read(&sound.source)?))
+    }
+
+    // Reads the sources of all passes and builds the config for running them. Every texture path
+    // becomes one entry of the config's textures, bound to the channels that read it.
+    pub fn shader_config(&self) -> error::Result<ShaderConfig> {
+        let (manifest, dir) = (&self.manifest, self.dir());
+        let read = |source: &str| {
+            let path = dir.join(source);
+            loader::read_shader_file(&path.to_string_lossy())
+        };
+
+        let mut config = ShaderConfig::new(&read(&manifest.image.source)?);
+        config.textures.clear();
+        config.image_path = Some(self.source_path(&manifest.image.source));
+        if let Some(ref common) = manifest.common {
+            config.common_src = read(common)?;
+            config.common_path = Some(self.source_path(common));
+        }
+
+        for (id, pass) in manifest.passes() {
+            match id {
+                PassId::Buffer(buffer) => {
+                    config.buffer_srcs[buffer.index()] = Some(read(&pass.source)?);
+                    config.buffer_paths[buffer.index()] = Some(self.source_path(&pass.source));
+                }
+                PassId::CubeA => {
+                    config.cube_src = Some(read(&pass.source)?);
+                    config.cube_path = Some(self.source_path(&pass.source));
+                }
+                PassId::Image => (),
+            }
+
+            let channels = [
+                &pass.channel0,
+                &pass.channel1,
+                &pass.channel2,
+                &pass.channel3,
+            ];
+            for (channel, manifest) in channels.iter().enumerate() {
+                let (input, sampler) =
+                    self.channel(&mut config.textures, id.name(), channel, manifest)?;
+                config.channel_bindings.push(ChannelBinding {
+                    pass: id,
+                    channel,
+                    input,
+                    sampler,
+                });
+            }
+        }
+
+        Ok(config)
+    }
+
+    // What the sound pass's channels read, and their samplers. Textures read by the channels are
+    // added to `textures`. Sound passes can only read textures, so other inputs read nothing.
+    pub fn sound_channels(
+        &self,
+        textures: &mut Vec<TextureSource>,
+    ) -> error::Result<[(ChannelInput, Option<SamplerInfo>); 4]> {
+        let sound = self.manifest.sound.as_ref().ok_or_else(|| {
+            InvalidManifestError::new(&self.path.to_string_lossy(), "no sound pass".to_string())
+        })?;
+        let channels = [
+            &sound.channel0,
+            &sound.channel1,
+            &sound.channel2,
+            &sound.channel3,
+        ];
+        let mut inputs = [(ChannelInput::Empty, None); 4];
+        for (channel, manifest) in channels.iter().enumerate() {
+            inputs[channel] = match self.channel(textures, "Sound", channel, manifest)? {
+                (ChannelInput::Texture(n), sampler) => (ChannelInput::Texture(n), sampler),
+                (ChannelInput::Empty, _) => (ChannelInput::Empty, None),
+                _ => {
+                    warn!(
+                        "Sound iChannel{}: sound passes can only read textures",
+                        channel
+                    );
+                    (ChannelInput::Empty, None)
+                }
+            };
+        }
+        Ok(inputs)
+    }
+
+    // What a channel of a pass reads, and its sampler. Textures are added to `textures` unless a
+    // channel already reads them.
+    fn channel(
+        &self,
+        textures: &mut Vec<TextureSource>,
+        pass: &str,
+        channel: usize,
+        manifest: &Option<ChannelManifest>,
+    ) -> error::Result<(ChannelInput, Option<SamplerInfo>)> {
+        let dir = self.dir();
+        let (input, sampler) = match *manifest {
+            Some(ChannelManifest::Texture {
+                path: Some(ref path),
+                filter,
+                wrap,
+                vflip,
+                srgb,
+                ..
+            }) => {
+                let source = TextureSource::File {
+                    path: dir.join(path).to_string_lossy().into(),
+                    vflip,
+                    srgb,
+                };
+                let index = texture_index(textures, source);
+                (ChannelInput::Texture(index), Some((filter, wrap)))
+            }
+            Some(ChannelManifest::Texture {
+                path: None,
+                ref url,
+                ..
+            }) => {
+                warn!(
+                    "{} iChannel{}: texture {} hasn't been downloaded",
+                    pass,
+                    channel,
+                    url.as_deref().unwrap_or("with no path")
+                );
+                (ChannelInput::Empty, None)
+            }
+            Some(ChannelManifest::Cubemap {
+                ref path,
+                ref url,
+                ref faces,
+                filter,
+                wrap,
+                vflip,
+                srgb,
+            }) => match cubemap_faces(dir, path.as_deref(), faces) {
+                Some(faces) => {
+                    let source = TextureSource::Cubemap { faces, vflip, srgb };
+                    let index = texture_index(textures, source);
+                    (ChannelInput::Texture(index), Some((filter, wrap)))
+                }
+                None => {
+                    warn!(
+                        "{} iChannel{}: cubemap {} hasn't been downloaded",
+                        pass,
+                        channel,
+                        url.as_deref().unwrap_or("with no path")
+                    );
+                    (ChannelInput::Empty, None)
+                }
+            },
+            Some(ChannelManifest::Volume {
+                ref path,
+                ref url,
+                ref slices,
+                size,
+                channels,
+                depth,
+                filter,
+                wrap,
+            }) => match self.volume_source(dir, path.as_deref(), slices, size, channels, depth)? {
+                Some(volume) => {
+                    let source = TextureSource::Volume(volume);
+                    let index = texture_index(textures, source);
+                    (ChannelInput::Texture(index), Some((filter, wrap)))
+                }
+                None => {
+                    warn!(
+                        "{} iChannel{}: volume {} hasn't been downloaded",
+                        pass,
+                        channel,
+                        url.as_deref().unwrap_or("with no path")
+                    );
+                    (ChannelInput::Empty, None)
+                }
+            },
+            Some(ChannelManifest::Buffer {
+                ref buffer,
+                filter,
+                wrap,
+            }) => {
+                let input = ChannelInput::from_pass_name(buffer).ok_or_else(|| {
+                    InvalidManifestError::new(
+                        &self.path.to_string_lossy(),
+                        format!("unknown buffer {} in {}", buffer, pass),
+                    )
+                })?;
+                (input, Some((filter, wrap)))
+            }
+            Some(ChannelManifest::Keyboard) => {
+                (ChannelInput::Keyboard, Some((Filter::Nearest, Wrap::Clamp)))
+            }
+            Some(ref unsupported) => {
+                warn!(
+                    "{} iChannel{}: {} inputs aren't supported yet",
+                    pass,
+                    channel,
+                    unsupported.kind()
+                );
+                (ChannelInput::Empty, None)
+            }
+            None => (ChannelInput::Empty, None),
+        };
+        let sampler = sampler.map(|(filter, wrap)| SamplerInfo::new(filter.method(), wrap.mode()));
+        Ok((input, sampler))
+    }
+
+    // Where a volume's texels come from (see ChannelManifest::Volume). None if it has no files.
+    fn volume_source(
+        &self,
+        dir: &Path,
+        path: Option<&str>,
+        slices: &[String],
+        size: Option<[u32; 3]>,
+        channels: Option<u8>,
+        depth: Option<u32>,
+    ) -> error::Result<Option<VolumeSource>> {
+        let resolve = |path: &str| dir.join(path).to_string_lossy().into_owned();
+        if !slices.is_empty() {
+            return Ok(Some(VolumeSource::Slices(
+                slices.iter().map(|slice| resolve(slice)).collect(),
+            )));
+        }
+
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let source = match (size, depth) {
+            (Some(size), _) => VolumeSource::Raw {
+                path: resolve(path),
+                size,
+                channels: channels.unwrap_or(1),
+            },
+            (None, Some(depth)) => VolumeSource::Stacked {
+                path: resolve(path),
+                depth,
+            },
+            (None, None) if path.ends_with(".bin") => VolumeSource::Bin(resolve(path)),
+            (None, None) => {
+                return Err(InvalidManifestError::new(
+                    &self.path.to_string_lossy(),
+                    format!(
+                        "volume {} needs a size (for raw texels) or depth (for stacked slices)",
+                        path
+                    ),
+                )
+                .into())
+            }
+        };
+        Ok(Some(source))
+    }
+}
+
+// Returns the index of the texture, adding it if no channel has read it yet.
+fn texture_index(textures: &mut Vec<TextureSource>, source: TextureSource) -> usize {
+    let existing = textures.iter().position(|texture| *texture == source);
+    existing.unwrap_or_else(|| {
+        textures.push(source);
+        textures.len() - 1
+    })
+}
+
+// Paths of a cubemap's images: its listed faces, or the face at the path and the five named after
+// it (e.g. sky.png, sky_1.png, ..., sky_5.png) if they exist, or else just the path. None if the
+// cubemap has no images.
+fn cubemap_faces(dir: &Path, path: Option<&str>, faces: &[String]) -> Option<Vec<String>> {
+    let resolve = |path: &str| dir.join(path).to_string_lossy().into_owned();
+    if !faces.is_empty() {
+        return Some(faces.iter().map(|face| resolve(face)).collect());
+    }
+
+    let path = path?;
+    let (stem, extension) = path.split_at(path.rfind('.').unwrap_or(path.len()));
+    let siblings: Vec<String> = (1..6)
+        .map(|face| format!("{}_{}{}", stem, face, extension))
+        .collect();
+    if siblings.iter().all(|sibling| dir.join(sibling).is_file()) {
+        Some(
+            Some(path)
+                .into_iter()
+                .chain(siblings.iter().map(String::as_str))
+                .map(resolve)
+                .collect(),
+        )
+    } else {
+        Some(vec![resolve(path)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    const SHADER: &str = "void mainImage(out vec4 c, in vec2 f) { c = vec4(1.0); }\n";
+
+    // Writes the manifest and (empty) shaders or images at the given paths to a directory of
+    // their own, and loads the manifest.
+    fn project(dir: &str, manifest: (&str, &str), files: &[&str]) -> error::Result<Project> {
+        let dir = env::temp_dir().join(format!("shadertoy_rs_{}_{}", process::id(), dir));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), SHADER).unwrap();
+        }
+        let path = dir.join(manifest.0);
+        fs::write(&path, manifest.1).unwrap();
+        Project::load(&path.to_string_lossy())
+    }
+
+    // The input and sampler of a channel of a pass.
+    fn binding(config: &ShaderConfig, pass: PassId, channel: usize) -> ChannelBinding {
+        *config
+            .channel_bindings
+            .iter()
+            .find(|binding| binding.pass == pass && binding.channel == channel)
+            .unwrap()
+    }
+
+    fn path(project: &Project, file: &str) -> String {
+        project.dir().join(file).to_string_lossy().into_owned()
+    }
+
+    fn file(project: &Project, file: &str, vflip: bool) -> TextureSource {
+        TextureSource::File {
+            path: path(project, file),
+            vflip,
+            srgb: false,
+        }
+    }
+
+    #[test]
+    fn parses_toml_and_json() {
+        let toml = r#"
+            title = "Fluid"
+            width = 800
+            common = "common.glsl"
+
+            [image]
+            source = "image.glsl"
+            channel0 = { type = "buffer", buffer = "buffer_a" }
+
+            [buffer_a]
+            source = "buffer_a.glsl"
+            channel1 = { type = "texture", path = "noise.png", filter = "nearest", wrap = "mirror" }
+        "#;
+        let json = r#"{
+            "title": "Fluid",
+            "width": 800,
+            "common": "common.glsl",
+            "image": {
+                "source": "image.glsl",
+                "channel0": {"type": "buffer", "buffer": "buffer_a"}
+            },
+            "buffer_a": {
+                "source": "buffer_a.glsl",
+                "channel1": {
+                    "type": "texture", "path": "noise.png", "filter": "nearest", "wrap": "mirror"
+                }
+            }
+        }"#;
+        let files = ["common.glsl", "image.glsl", "buffer_a.glsl", "noise.png"];
+
+        for &(dir, manifest) in [
+            ("toml", ("fluid.toml", toml)),
+            ("json", ("fluid.json", json)),
+        ]
+        .iter()
+        {
+            let project = project(dir, manifest, &files).unwrap();
+            assert_eq!(project.manifest.title.as_deref(), Some("Fluid"));
+            assert_eq!(project.manifest.width, Some(800));
+            assert_eq!(project.manifest.height, None);
+
+            let config = project.shader_config().unwrap();
+            assert_eq!(config.image_src, SHADER);
+            assert_eq!(config.common_src, SHADER);
+            assert_eq!(config.buffer_srcs[0].as_deref(), Some(SHADER));
+            assert_eq!(config.buffer_srcs[1], None);
+            assert_eq!(config.image_path, Some(path(&project, "image.glsl")));
+            assert_eq!(
+                config.buffer_paths[0],
+                Some(path(&project, "buffer_a.glsl"))
+            );
+            assert_eq!(config.textures, [file(&project, "noise.png", true)]);
+
+            // Four bindings for each pass, with unlisted channels reading nothing.
+            assert_eq!(config.channel_bindings.len(), 8);
+            let image = binding(&config, PassId::Image, 0);
+            assert_eq!(image.input, ChannelInput::Buffer(BufferId::A));
+            assert_eq!(
+                image.sampler,
+                Some(SamplerInfo::new(FilterMethod::Bilinear, WrapMode::Clamp))
+            );
+            let buffer = binding(&config, PassId::Buffer(BufferId::A), 1);
+            assert_eq!(buffer.input, ChannelInput::Texture(0));
+            assert_eq!(
+                buffer.sampler,
+                Some(SamplerInfo::new(FilterMethod::Scale, WrapMode::Mirror))
+            );
+            let empty = binding(&config, PassId::Buffer(BufferId::A), 0);
+            assert_eq!((empty.input, empty.sampler), (ChannelInput::Empty, None));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let manifests = [
+            "titel = \"x\"\n[image]\nsource = \"image.glsl\"\n",
+            "[image]\nsource = \"image.glsl\"\nchanel0 = { type = \"keyboard\" }\n",
+            "[image]\nsource = \"image.glsl\"\nchannel0 = { type = \"webcam\" }\n",
+            "[image]\nsource = \"image.glsl\"\n[buffer_e]\nsource = \"image.glsl\"\n",
+        ];
+        for manifest in manifests.iter() {
+            let err = project("unknown", ("project.toml", manifest), &[]).unwrap_err();
+            assert!(err.to_string().contains("unknown"), "{}", err);
+        }
+        let json = r#"{"image": {"source": "image.glsl"}, "sound": {"source": "s.glsl", "x": 1}}"#;
+        let err = project("unknown", ("project.json", json), &[]).unwrap_err();
+        assert!(err.to_string().contains("unknown field `x`"), "{}", err);
+    }
+
+    #[test]
+    fn maps_channels() {
+        let manifest = r#"
+            [image]
+            source = "image.glsl"
+            channel0 = { type = "texture", path = "a.png" }
+            channel1 = { type = "texture", path = "b.png", vflip = false }
+            channel2 = { type = "keyboard" }
+            channel3 = { type = "cubemap", faces = ["1.png", "2.png", "3.png", "4.png", "5.png", "6.png"] }
+
+            [buffer_a]
+            source = "image.glsl"
+            channel0 = { type = "texture", path = "a.png" }
+            channel1 = { type = "texture", path = "a.png", vflip = false }
+            channel2 = { type = "buffer", buffer = "cube_a" }
+            channel3 = { type = "texture", url = "/media/a/noise.png" }
+
+            [cube_a]
+            source = "image.glsl"
+            channel0 = { type = "buffer", buffer = "buffer_a" }
+            channel1 = { type = "audio", path = "music.mp3" }
+        "#;
+        let project = project("channels", ("project.toml", manifest), &["image.glsl"]).unwrap();
+        let config = project.shader_config().unwrap();
+
+        // The same file read the same way is loaded once.
+        let faces = ["1.png", "2.png", "3.png", "4.png", "5.png", "6.png"];
+        assert_eq!(
+            config.textures,
+            [
+                file(&project, "a.png", true),
+                file(&project, "a.png", false),
+                file(&project, "b.png", false),
+                TextureSource::Cubemap {
+                    faces: faces.iter().map(|face| path(&project, face)).collect(),
+                    vflip: false,
+                    srgb: false,
+                },
+            ]
+        );
+
+        let buffer_a = PassId::Buffer(BufferId::A);
+        let inputs = [
+            (buffer_a, 0, ChannelInput::Texture(0)),
+            (buffer_a, 1, ChannelInput::Texture(1)),
+            (buffer_a, 2, ChannelInput::CubeA),
+            (buffer_a, 3, ChannelInput::Empty),
+            (PassId::CubeA, 0, ChannelInput::Buffer(BufferId::A)),
+            (PassId::CubeA, 1, ChannelInput::Empty),
+            (PassId::Image, 0, ChannelInput::Texture(0)),
+            (PassId::Image, 1, ChannelInput::Texture(2)),
+            (PassId::Image, 2, ChannelInput::Keyboard),
+            (PassId::Image, 3, ChannelInput::Texture(3)),
+        ];
+        for &(pass, channel, input) in inputs.iter() {
+            assert_eq!(
+                binding(&config, pass, channel).input,
+                input,
+                "{:?} {}",
+                pass,
+                channel
+            );
+        }
+        assert_eq!(
+            binding(&config, PassId::Image, 0).sampler,
+            Some(SamplerInfo::new(FilterMethod::Trilinear, WrapMode::Tile))
+        );
+        assert_eq!(
+            binding(&config, PassId::Image, 2).sampler,
+            Some(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp))
+        );
+        assert_eq!(
+            binding(&config, PassId::Image, 3).sampler,
+            Some(SamplerInfo::new(FilterMethod::Trilinear, WrapMode::Clamp))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_buffers() {
+        let manifest = r#"
+            [image]
+            source = "image.glsl"
+            channel0 = { type = "buffer", buffer = "buffer_e" }
+        "#;
+        let project = project("buffers", ("project.toml", manifest), &["image.glsl"]).unwrap();
+        let err = project.shader_config().unwrap_err();
+        assert!(
+            err.to_string().contains("unknown buffer buffer_e in Image"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn finds_cubemap_faces() {
+        let files = [
+            "sky.png",
+            "sky_1.png",
+            "sky_2.png",
+            "sky_3.png",
+            "sky_4.png",
+            "sky_5.png",
+            "cross.png",
+            "lone.png",
+            "lone_1.png",
+        ];
+        let project = project(
+            "cubemaps",
+            ("project.toml", "[image]\nsource = \"x\"\n"),
+            &files,
+        )
+        .unwrap();
+        let dir = project.dir();
+        let paths =
+            |files: &[&str]| -> Vec<String> { files.iter().map(|f| path(&project, f)).collect() };
+
+        assert_eq!(
+            cubemap_faces(dir, Some("sky.png"), &[]),
+            Some(paths(&files[..6]))
+        );
+        // Without all five siblings, the image has the faces laid out in it.
+        assert_eq!(
+            cubemap_faces(dir, Some("cross.png"), &[]),
+            Some(paths(&["cross.png"]))
+        );
+        assert_eq!(
+            cubemap_faces(dir, Some("lone.png"), &[]),
+            Some(paths(&["lone.png"]))
+        );
+
+        let faces: Vec<String> = files[..6].iter().rev().map(|f| f.to_string()).collect();
+        assert_eq!(
+            cubemap_faces(dir, Some("sky.png"), &faces),
+            Some(paths(&[
+                "sky_5.png",
+                "sky_4.png",
+                "sky_3.png",
+                "sky_2.png",
+                "sky_1.png",
+                "sky.png"
+            ]))
+        );
+        assert_eq!(cubemap_faces(dir, None, &[]), None);
+    }
+
+    #[test]
+    fn finds_volume_sources() {
+        let project = project(
+            "volumes",
+            ("project.toml", "[image]\nsource = \"x\"\n"),
+            &[],
+        )
+        .unwrap();
+        let dir = project.dir();
+        let source = |path, slices: &[String], size, channels, depth| {
+            project.volume_source(dir, path, slices, size, channels, depth)
+        };
+
+        let slices = vec!["s0.png".to_string(), "s1.png".to_string()];
+        assert_eq!(
+            source(Some("v.bin"), &slices, None, None, None).unwrap(),
+            Some(VolumeSource::Slices(vec![
+                path(&project, "s0.png"),
+                path(&project, "s1.png")
+            ]))
+        );
+        assert_eq!(
+            source(Some("v.bin"), &[], None, None, None).unwrap(),
+            Some(VolumeSource::Bin(path(&project, "v.bin")))
+        );
+        assert_eq!(
+            source(Some("v.raw"), &[], Some([4, 2, 1]), None, None).unwrap(),
+            Some(VolumeSource::Raw {
+                path: path(&project, "v.raw"),
+                size: [4, 2, 1],
+                channels: 1,
+            })
+        );
+        assert_eq!(
+            source(Some("v.png"), &[], None, Some(3), Some(8)).unwrap(),
+            Some(VolumeSource::Stacked {
+                path: path(&project, "v.png"),
+                depth: 8,
+            })
+        );
+        assert_eq!(source(None, &[], None, None, None).unwrap(), None);
+
+        let err = source(Some("v.raw"), &[], None, Some(3), None).unwrap_err();
+        assert!(
+            err.to_string().contains("volume v.raw needs a size"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,990 @@
+use diagnostics::ComposedSource;
+use error::{self, CreatePipelineError, MissingBufferError, MissingTextureError};
+use gfx;
+use gfx::format::{Formatted, Rgba8};
+use gfx::memory::{Bind, Typed, Usage};
+use gfx::texture;
+use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+use gfx::traits::FactoryExt;
+use keyboard::{self, Keyboard};
+use loader;
+use pass::{self, BufferId, ChannelBinding, ChannelInput, PassId};
+
+use chrono::{Datelike, Local, Timelike};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureId {
+    Zero,
+    One,
+    Two,
+    Three,
+}
+
+// Where a texture read by a channel comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureSource {
+    // One of the four bundled default textures.
+    Default(TextureId),
+
+    // An image file (anything the image crate can decode). Images are normally flipped so that
+    // they appear upright, with (0, 0) at the bottom left; vflip: false keeps the top row at v = 0.
+    // With srgb, the image is treated as sRGB-encoded and converted to linear when sampled.
+    File {
+        path: String,
+        vflip: bool,
+        srgb: bool,
+    },
+
+    // Raw 8-bit RGBA pixels, top row first.
+    Rgba8 {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+
+    // A cubemap, from six square images of its faces in the order +X, -X, +Y, -Y, +Z, -Z, or from
+    // a single image with the faces laid out as a cross (4x3 or 3x4 faces) or a strip (6x1 or 1x6
+    // faces, in the same order). Faces are loaded top row first unless vflip is set.
+    Cubemap {
+        faces: Vec<String>,
+        vflip: bool,
+        srgb: bool,
+    },
+
+    // A 3D texture.
+    Volume(VolumeSource),
+}
+
+impl TextureSource {
+    pub fn kind(&self) -> TextureKind {
+        match *self {
+            TextureSource::Cubemap { .. } => TextureKind::Cube,
+            TextureSource::Volume(_) => TextureKind::D3,
+            _ => TextureKind::D2,
+        }
+    }
+}
+
+// Where the texels of a 3D texture come from. Texels are stored a row at a time, from the first
+// row of the first slice (at uvw = 0) up. Fewer than four channels read as they would on
+// shadertoy.com: missing color channels are 0 and alpha is 1.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VolumeSource {
+    // A file in shadertoy.com's binary volume format (.bin): a 20-byte header with the signature
+    // "BIN\0", the width, height and depth (as u32s), the number of channels (u8), a layout byte
+    // and the format (u16, 0 for u8 texels or 10 for f32 texels), followed by the texels.
+    Bin(String),
+
+    // A file of nothing but 8-bit texels, of the given size and number of channels.
+    Raw {
+        path: String,
+        size: [u32; 3],
+        channels: u8,
+    },
+
+    // A single image with `depth` slices stacked from top to bottom.
+    Stacked {
+        path: String,
+        depth: u32,
+    },
+
+    // Images of each slice, all the same size.
+    Slices(Vec<String>),
+}
+
+// The type of texture a channel reads, which decides how iChannelN is declared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    D2,
+    Cube,
+    D3,
+}
+
+impl TextureKind {
+    // GLSL type of a sampler for the texture.
+    pub fn sampler_type(self) -> &'static str {
+        match self {
+            TextureKind::D2 => "sampler2D",
+            TextureKind::Cube => "samplerCube",
+            TextureKind::D3 => "sampler3D",
+        }
+    }
+}
+
+// Everything needed to render a shader: the source of each pass and what its channels read.
+#[derive(Clone, Debug)]
+pub struct ShaderConfig {
+    // Source of the image pass, defining mainImage.
+    pub image_src: String,
+
+    // Code shared by all passes (Shadertoy's "Common" tab). May be empty.
+    pub common_src: String,
+
+    // Sources of the Buffer A-D passes, indexed by BufferId::index(). None if the pass is unused.
+    pub buffer_srcs: [Option<String>; 4],
+
+    // Source of the Cube A pass, defining mainCubemap. None if the pass is unused.
+    pub cube_src: Option<String>,
+
+    // Files the image, common, buffer and Cube A sources were read from, for error messages. None
+    // if a source didn't come from a file, in which case it's called by its tab on Shadertoy.
+    pub image_path: Option<String>,
+    pub common_path: Option<String>,
+    pub buffer_paths: [Option<String>; 4],
+    pub cube_path: Option<String>,
+
+    // Textures read through ChannelInput::Texture(n), which indexes into this list.
+    pub textures: Vec<TextureSource>,
+
+    // Default sampler settings for iChannel0-3, unless overridden by a channel binding.
+    pub samplers: [SamplerInfo; 4],
+
+    // Channel bindings that override the default of iChannelN reading the n-th texture.
+    pub channel_bindings: Vec<ChannelBinding>,
+
+    // Reported through iSampleRate.
+    pub sample_rate: u32,
+}
+
+impl ShaderConfig {
+    // A single-pass shader reading the default textures, with mipmapped, repeating samplers.
+    pub fn new(image_src: &str) -> ShaderConfig {
+        let sampler = SamplerInfo::new(FilterMethod::Mipmap, WrapMode::Tile);
+        ShaderConfig {
+            image_src: image_src.to_string(),
+            common_src: String::new(),
+            buffer_srcs: [None, None, None, None],
+            cube_src: None,
+            image_path: None,
+            common_path: None,
+            buffer_paths: [None, None, None, None],
+            cube_path: None,
+            textures: vec![
+                TextureSource::Default(TextureId::Zero),
+                TextureSource::Default(TextureId::One),
+                TextureSource::Default(TextureId::Two),
+                TextureSource::Default(TextureId::Three),
+            ],
+            samplers: [sampler; 4],
+            channel_bindings: Vec::new(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    pub fn buffer_src(&self, id: BufferId) -> Option<&String> {
+        self.buffer_srcs[id.index()].as_ref()
+    }
+
+    // Name of the pass's source in error messages.
+    fn source_name(&self, id: PassId) -> &str {
+        let path = match id {
+            PassId::Buffer(buffer) => self.buffer_paths[buffer.index()].as_ref(),
+            PassId::CubeA => self.cube_path.as_ref(),
+            PassId::Image => self.image_path.as_ref(),
+        };
+        path.map_or(id.name(), String::as_str)
+    }
+
+    // Type of texture a channel of the pass reads. Buffers, the keyboard and empty channels are 2D.
+    fn channel_kind(&self, id: PassId, channel: usize) -> TextureKind {
+        match pass::channel_input(&self.channel_bindings, id, channel) {
+            ChannelInput::Texture(n) => self.textures.get(n).map_or(TextureKind::D2, |t| t.kind()),
+            ChannelInput::CubeA => TextureKind::Cube,
+            _ => TextureKind::D2,
+        }
+    }
+
+    // Wraps the pass's source in the generated code around it, along with the common code.
+    fn format_src(&self, id: PassId, src: &str) -> ComposedSource {
+        let common_name = self.common_path.as_deref().unwrap_or("Common");
+        let channel_kinds = [0, 1, 2, 3].map(|channel| self.channel_kind(id, channel));
+        let format = match id {
+            PassId::CubeA => loader::format_cube_shader_src,
+            _ => loader::format_shader_src,
+        };
+        format(
+            channel_kinds,
+            common_name,
+            &self.common_src,
+            self.source_name(id),
+            src,
+        )
+    }
+}
+
+// Sample rate used by shadertoy.com, and reported through iSampleRate by default.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// Format of the image pass when rendering to a window.
+pub type ColorFormat = gfx::format::Rgba8;
+
+// Buffer passes render into floating point targets so they can store arbitrary state.
+type BufferFormat = gfx::format::Rgba32F;
+
+// The Cube A pass renders into a half float cubemap with faces of CUBE_SIZE, as on shadertoy.com.
+type CubeFormat = gfx::format::Rgba16F;
+const CUBE_SIZE: texture::Size = 1024;
+
+pub type ChannelView<R> = gfx::handle::ShaderResourceView<R, [f32; 4]>;
+
+// A channel's texture along with its resolution, as exposed through iChannelResolution.
+pub type ChannelTexture<R> = (ChannelView<R>, [f32; 3]);
+
+gfx_defines! {
+    vertex Vertex {
+        pos: [f32; 2] = "position",
+    }
+
+    pipeline pipe {
+        // Vertex buffer.
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+
+        // Uniforms.
+        i_global_time: gfx::Global<f32> = "iGlobalTime",
+        i_time: gfx::Global<f32> = "iTime",
+        i_time_delta: gfx::Global<f32> = "iTimeDelta",
+        i_frame_rate: gfx::Global<f32> = "iFrameRate",
+        i_date: gfx::Global<[f32; 4]> = "iDate",
+        i_resolution: gfx::Global<[f32; 3]> = "iResolution",
+        i_mouse: gfx::Global<[f32; 4]> = "iMouse",
+        i_frame: gfx::Global<i32> = "iFrame",
+        i_sample_rate: gfx::Global<f32> = "iSampleRate",
+        i_channel0: gfx::TextureSampler<[f32; 4]> = "iChannel0",
+        i_channel1: gfx::TextureSampler<[f32; 4]> = "iChannel1",
+        i_channel2: gfx::TextureSampler<[f32; 4]> = "iChannel2",
+        i_channel3: gfx::TextureSampler<[f32; 4]> = "iChannel3",
+
+        // Elements of iChannelResolution and iChannelTime. The prefix copies these into the
+        // arrays, since gfx has no support for array uniforms.
+        i_channel_resolution0: gfx::Global<[f32; 3]> = "iChannelResolution0",
+        i_channel_resolution1: gfx::Global<[f32; 3]> = "iChannelResolution1",
+        i_channel_resolution2: gfx::Global<[f32; 3]> = "iChannelResolution2",
+        i_channel_resolution3: gfx::Global<[f32; 3]> = "iChannelResolution3",
+        i_channel_time0: gfx::Global<f32> = "iChannelTime0",
+        i_channel_time1: gfx::Global<f32> = "iChannelTime1",
+        i_channel_time2: gfx::Global<f32> = "iChannelTime2",
+        i_channel_time3: gfx::Global<f32> = "iChannelTime3",
+
+        // Face of the cubemap the Cube A pass is rendering.
+        cube_face: gfx::Global<i32> = "_cubeFace",
+
+        // Output color. Raw so the same pipeline can target both the window and float buffers.
+        frag_color: gfx::RawRenderTarget = (
+            "fragColor",
+            ColorFormat::get_format(),
+            gfx::state::ColorMask::all(),
+            None,
+        ),
+    }
+}
+
+pub const SCREEN: [Vertex; 4] = [
+    Vertex { pos: [1.0, 1.0] },   // Top right.
+    Vertex { pos: [-1.0, 1.0] },  // Top left.
+    Vertex { pos: [-1.0, -1.0] }, // Bottom left.
+    Vertex { pos: [1.0, -1.0] },  // Bottom right.
+];
+
+pub const SCREEN_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+// A compiled render pass along with the inputs and samplers of its four channels.
+struct Pass<R: gfx::Resources> {
+    id: PassId,
+    pso: gfx::PipelineState<R, pipe::Meta>,
+    inputs: [ChannelInput; 4],
+    samplers: [gfx::handle::Sampler<R>; 4],
+}
+
+// Render targets for a buffer pass. There are two so that a buffer can read its own previous frame
+// while rendering the current one.
+struct BufferTargets<R: gfx::Resources> {
+    views: [ChannelView<R>; 2],
+    targets: [gfx::handle::RenderTargetView<R, BufferFormat>; 2],
+
+    // Index of the target holding the most recently rendered frame.
+    current: usize,
+}
+
+impl<R: gfx::Resources> BufferTargets<R> {
+    fn new<F, C>(
+        factory: &mut F,
+        encoder: &mut gfx::Encoder<R, C>,
+        width: f32,
+        height: f32,
+    ) -> error::Result<BufferTargets<R>>
+    where
+        F: gfx::Factory<R>,
+        C: gfx::CommandBuffer<R>,
+    {
+        let (w, h) = (width as texture::Size, height as texture::Size);
+        let (_, view0, target0) = factory.create_render_target::<BufferFormat>(w, h)?;
+        let (_, view1, target1) = factory.create_render_target::<BufferFormat>(w, h)?;
+
+        // Buffers start out cleared, as on shadertoy.com.
+        encoder.clear(&target0, [0.0; 4]);
+        encoder.clear(&target1, [0.0; 4]);
+
+        Ok(BufferTargets {
+            views: [view0, view1],
+            targets: [target0, target1],
+            current: 0,
+        })
+    }
+
+    // Most recently rendered frame.
+    fn front(&self) -> &ChannelView<R> {
+        &self.views[self.current]
+    }
+
+    // Target for the frame being rendered.
+    fn back(&self) -> &gfx::handle::RenderTargetView<R, BufferFormat> {
+        &self.targets[1 - self.current]
+    }
+
+    fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+}
+
+// Targets for the faces of a cubemap, in the order +X, -X, +Y, -Y, +Z, -Z.
+type CubeFaces<R> = Vec<gfx::handle::RenderTargetView<R, CubeFormat>>;
+
+// Render targets for the Cube A pass: two cubemaps, like BufferTargets, with a target for each
+// face.
+struct CubeTargets<R: gfx::Resources> {
+    views: [ChannelView<R>; 2],
+    faces: [CubeFaces<R>; 2],
+
+    // Index of the cubemap holding the most recently rendered frame.
+    current: usize,
+}
+
+impl<R: gfx::Resources> CubeTargets<R> {
+    fn new<F, C>(factory: &mut F, encoder: &mut gfx::Encoder<R, C>) -> error::Result<CubeTargets<R>>
+    where
+        F: gfx::Factory<R>,
+        C: gfx::CommandBuffer<R>,
+    {
+        let (view0, faces0) = CubeTargets::create_cubemap(factory, encoder)?;
+        let (view1, faces1) = CubeTargets::create_cubemap(factory, encoder)?;
+        Ok(CubeTargets {
+            views: [view0, view1],
+            faces: [faces0, faces1],
+            current: 0,
+        })
+    }
+
+    // Creates a cleared cubemap, along with targets for its faces.
+    fn create_cubemap<F, C>(
+        factory: &mut F,
+        encoder: &mut gfx::Encoder<R, C>,
+    ) -> error::Result<(ChannelView<R>, CubeFaces<R>)>
+    where
+        F: gfx::Factory<R>,
+        C: gfx::CommandBuffer<R>,
+    {
+        let texture = factory.create_texture(
+            texture::Kind::Cube(CUBE_SIZE),
+            1,
+            Bind::SHADER_RESOURCE | Bind::RENDER_TARGET,
+            Usage::Data,
+            Some(gfx::format::ChannelType::Float),
+        )?;
+        let view = factory.view_texture_as_shader_resource::<CubeFormat>(
+            &texture,
+            (0, 0),
+            gfx::format::Swizzle::new(),
+        )?;
+        let mut faces = Vec::new();
+        for face in 0..6 {
+            let target = factory.view_texture_as_render_target(&texture, 0, Some(face))?;
+            encoder.clear(&target, [0.0; 4]);
+            faces.push(target);
+        }
+        Ok((view, faces))
+    }
+
+    // Most recently rendered frame.
+    fn front(&self) -> &ChannelView<R> {
+        &self.views[self.current]
+    }
+
+    // Targets for the frame being rendered.
+    fn back(&self) -> &[gfx::handle::RenderTargetView<R, CubeFormat>] {
+        &self.faces[1 - self.current]
+    }
+
+    fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+}
+
+fn create_pass<F, R>(
+    factory: &mut F,
+    config: &ShaderConfig,
+    id: PassId,
+    vert_src_buf: &[u8],
+    frag_src: &ComposedSource,
+    image_format: gfx::format::Format,
+) -> error::Result<Pass<R>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    let format = match id {
+        PassId::Buffer(_) => BufferFormat::get_format(),
+        PassId::CubeA => CubeFormat::get_format(),
+        PassId::Image => image_format,
+    };
+    let init = pipe::Init {
+        frag_color: ("fragColor", format, gfx::state::ColorMask::all(), None),
+        ..pipe::new()
+    };
+
+    let pso = factory
+        .create_pipeline_simple(vert_src_buf, frag_src.as_bytes(), init)
+        .map_err(|err| CreatePipelineError::new(id.name(), frag_src.explain(&err)))?;
+
+    let bindings = &config.channel_bindings;
+    let inputs = [0, 1, 2, 3].map(|channel| pass::channel_input(bindings, id, channel));
+    let samplers = [0, 1, 2, 3].map(|channel| {
+        let default = config.samplers[channel];
+        factory.create_sampler(pass::channel_sampler(bindings, id, channel, default))
+    });
+
+    Ok(Pass {
+        id,
+        pso,
+        inputs,
+        samplers,
+    })
+}
+
+// Creates the buffer passes (in order A-D) followed by the Cube A and image passes.
+fn create_passes<F, R>(
+    factory: &mut F,
+    config: &ShaderConfig,
+    vert_src_buf: &[u8],
+    image_format: gfx::format::Format,
+) -> error::Result<Vec<Pass<R>>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+{
+    let mut passes = Vec::new();
+
+    for &id in BufferId::ALL.iter() {
+        if let Some(src) = config.buffer_src(id) {
+            passes.push(create_pass(
+                factory,
+                config,
+                PassId::Buffer(id),
+                vert_src_buf,
+                &config.format_src(PassId::Buffer(id), src),
+                image_format,
+            )?);
+        }
+    }
+    if let Some(ref src) = config.cube_src {
+        passes.push(create_pass(
+            factory,
+            config,
+            PassId::CubeA,
+            vert_src_buf,
+            &config.format_src(PassId::CubeA, src),
+            image_format,
+        )?);
+    }
+    passes.push(create_pass(
+        factory,
+        config,
+        PassId::Image,
+        vert_src_buf,
+        &config.format_src(PassId::Image, &config.image_src),
+        image_format,
+    )?);
+
+    // Make sure every buffer read by a channel actually gets rendered, and every texture exists.
+    for pass in &passes {
+        for input in pass.inputs.iter() {
+            match *input {
+                ChannelInput::Buffer(id) if config.buffer_src(id).is_none() => {
+                    return Err(MissingBufferError::new(id.name()).into());
+                }
+                ChannelInput::CubeA if config.cube_src.is_none() => {
+                    return Err(MissingBufferError::new(PassId::CubeA.name()).into());
+                }
+                ChannelInput::Texture(n) if n >= config.textures.len() => {
+                    return Err(MissingTextureError::new(n).into());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(passes)
+}
+
+// Creates render targets for each buffer pass.
+fn create_buffers<F, R, C>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    passes: &[Pass<R>],
+    width: f32,
+    height: f32,
+) -> error::Result<Vec<Option<BufferTargets<R>>>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+{
+    BufferId::ALL
+        .iter()
+        .map(|&id| {
+            if passes.iter().any(|pass| pass.id == PassId::Buffer(id)) {
+                BufferTargets::new(factory, encoder, width, height).map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+        .collect()
+}
+
+// Creates the render targets for the Cube A pass, if there is one.
+fn create_cube<F, R, C>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    passes: &[Pass<R>],
+) -> error::Result<Option<CubeTargets<R>>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+{
+    if passes.iter().any(|pass| pass.id == PassId::CubeA) {
+        CubeTargets::new(factory, encoder).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+// Texture read by keyboard channels, updated when the keyboard's state changes.
+struct KeyboardTexture<R: gfx::Resources> {
+    texture: gfx::handle::Texture<R, <Rgba8 as Formatted>::Surface>,
+    view: ChannelView<R>,
+
+    // Texels of the latest state, and whether they still need to be uploaded.
+    texels: Vec<[u8; 4]>,
+    dirty: bool,
+}
+
+impl<R: gfx::Resources> KeyboardTexture<R> {
+    // Creates the texture with no keys down.
+    fn new<F>(factory: &mut F) -> error::Result<KeyboardTexture<R>>
+    where
+        F: gfx::Factory<R>,
+    {
+        let kind = texture::Kind::D2(
+            keyboard::WIDTH as texture::Size,
+            keyboard::HEIGHT as texture::Size,
+            texture::AaMode::Single,
+        );
+        let texture = factory.create_texture(
+            kind,
+            1,
+            Bind::SHADER_RESOURCE,
+            Usage::Dynamic,
+            Some(gfx::format::ChannelType::Unorm),
+        )?;
+        let view = factory.view_texture_as_shader_resource::<Rgba8>(
+            &texture,
+            (0, 0),
+            gfx::format::Swizzle::new(),
+        )?;
+        Ok(KeyboardTexture {
+            texture,
+            view,
+            texels: Keyboard::new().texels(),
+            dirty: true,
+        })
+    }
+
+    // Marks the texels for upload if they differ from the last ones.
+    fn set(&mut self, texels: Vec<[u8; 4]>) {
+        if texels != self.texels {
+            self.texels = texels;
+            self.dirty = true;
+        }
+    }
+
+    fn upload<C>(&mut self, encoder: &mut gfx::Encoder<R, C>)
+    where
+        C: gfx::CommandBuffer<R>,
+    {
+        if self.dirty {
+            let info = self.texture.get_info().to_image_info(0);
+            encoder
+                .update_texture::<_, Rgba8>(&self.texture, None, info, &self.texels)
+                .expect("keyboard texels match the texture's size");
+            self.dirty = false;
+        }
+    }
+}
+
+// Loads the shader's textures, with mipmaps.
+fn load_textures<F, R, C>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    config: &ShaderConfig,
+) -> error::Result<Vec<ChannelTexture<R>>>
+where
+    F: gfx::Factory<R>,
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+{
+    config
+        .textures
+        .iter()
+        .map(|source| {
+            let texture = loader::load_texture(source, factory)?;
+            encoder.generate_mipmap(&texture.0);
+            Ok(texture)
+        })
+        .collect()
+}
+
+// Local date as (year, month [0-11], day [1-31], seconds since midnight), matching iDate.
+pub fn current_date() -> [f32; 4] {
+    let now = Local::now();
+    let seconds = now.num_seconds_from_midnight() as f32 + now.nanosecond() as f32 / 1e9;
+    [
+        now.year() as f32,
+        now.month0() as f32,
+        now.day() as f32,
+        seconds,
+    ]
+}
+
+// Buffers always match the window size, given as `resolution`.
+fn channel_texture<R: gfx::Resources>(
+    input: ChannelInput,
+    textures: &[ChannelTexture<R>],
+    empty: &ChannelView<R>,
+    keyboard: &ChannelView<R>,
+    buffers: &[Option<BufferTargets<R>>],
+    cube: Option<&CubeTargets<R>>,
+    resolution: [f32; 3],
+) -> ChannelTexture<R> {
+    match input {
+        ChannelInput::Texture(n) => textures[n].clone(),
+        ChannelInput::Empty => (empty.clone(), [0.0; 3]),
+        ChannelInput::Keyboard => (
+            keyboard.clone(),
+            [keyboard::WIDTH as f32, keyboard::HEIGHT as f32, 1.0],
+        ),
+        ChannelInput::Buffer(id) => {
+            let view = buffers[id.index()]
+                .as_ref()
+                .expect("buffer inputs are validated in create_passes")
+                .front()
+                .clone();
+            (view, resolution)
+        }
+        ChannelInput::CubeA => {
+            let view = cube
+                .expect("Cube A inputs are validated in create_passes")
+                .front()
+                .clone();
+            (view, [CUBE_SIZE as f32, CUBE_SIZE as f32, 1.0])
+        }
+    }
+}
+
+// Renders a shader (all of its passes) into a caller-provided target, independent of any window.
+pub struct Renderer<R: gfx::Resources> {
+    vert_src_buf: Vec<u8>,
+    image_format: gfx::format::Format,
+    passes: Vec<Pass<R>>,
+    buffers: Vec<Option<BufferTargets<R>>>,
+    cube: Option<CubeTargets<R>>,
+    textures: Vec<ChannelTexture<R>>,
+    slice: gfx::Slice<R>,
+
+    // Bound to empty channels.
+    empty: ChannelView<R>,
+
+    keyboard: KeyboardTexture<R>,
+
+    data: pipe::Data<R>,
+    width: f32,
+    height: f32,
+
+    // Fixed value of iDate, or None to use the current date.
+    date: Option<[f32; 4]>,
+}
+
+impl<R: gfx::Resources> Renderer<R> {
+    // Creates a renderer for the shader, drawing at the given resolution. The image pass's output
+    // has format `image_format` (e.g. ColorFormat for a window). `target` is only used until the
+    // first call to render.
+    pub fn new<F, C>(
+        factory: &mut F,
+        encoder: &mut gfx::Encoder<R, C>,
+        config: &ShaderConfig,
+        image_format: gfx::format::Format,
+        width: f32,
+        height: f32,
+        target: &gfx::handle::RawRenderTargetView<R>,
+    ) -> error::Result<Renderer<R>>
+    where
+        F: gfx::Factory<R>,
+        C: gfx::CommandBuffer<R>,
+    {
+        let vert_src_buf = loader::load_vertex_shader();
+        let passes = create_passes(factory, config, &vert_src_buf, image_format)?;
+        let buffers = create_buffers(factory, encoder, &passes, width, height)?;
+        let cube = create_cube(factory, encoder, &passes)?;
+
+        let (vertex_buffer, slice) =
+            factory.create_vertex_buffer_with_slice(&SCREEN, &SCREEN_INDICES[..]);
+
+        let textures = load_textures(factory, encoder, config)?;
+
+        // A single transparent black texel.
+        let kind = texture::Kind::D2(1, 1, texture::AaMode::Single);
+        let (_, empty) = factory.create_texture_immutable_u8::<gfx::format::Rgba8>(
+            kind,
+            texture::Mipmap::Provided,
+            &[&[0; 4]],
+        )?;
+        let sampler = factory.create_sampler_linear();
+        let keyboard = KeyboardTexture::new(factory)?;
+
+        let data = pipe::Data {
+            vbuf: vertex_buffer,
+
+            i_global_time: 0.0,
+            i_time: 0.0,
+            i_time_delta: 0.0,
+            i_frame_rate: 0.0,
+            i_date: current_date(),
+            i_resolution: [width, height, width / height],
+            i_mouse: [0.0; 4],
+            i_frame: -1,
+            i_sample_rate: config.sample_rate as f32,
+
+            // Channels are bound per pass when rendering.
+            i_channel0: (empty.clone(), sampler.clone()),
+            i_channel1: (empty.clone(), sampler.clone()),
+            i_channel2: (empty.clone(), sampler.clone()),
+            i_channel3: (empty.clone(), sampler),
+
+            i_channel_resolution0: [0.0; 3],
+            i_channel_resolution1: [0.0; 3],
+            i_channel_resolution2: [0.0; 3],
+            i_channel_resolution3: [0.0; 3],
+
+            // Only animated inputs (video, music) advance their channel time, and none of the
+            // supported inputs are animated.
+            i_channel_time0: 0.0,
+            i_channel_time1: 0.0,
+            i_channel_time2: 0.0,
+            i_channel_time3: 0.0,
+
+            cube_face: 0,
+
+            frag_color: target.clone(),
+        };
+
+        Ok(Renderer {
+            vert_src_buf,
+            image_format,
+            passes,
+            buffers,
+            cube,
+            textures,
+            slice,
+            empty,
+            keyboard,
+            data,
+            width,
+            height,
+            date: None,
+        })
+    }
+
+    // Recompiles all passes and reloads textures from a new config, restarting from frame 0. On
+    // failure, the previous passes keep running.
+    pub fn reload<F, C>(
+        &mut self,
+        factory: &mut F,
+        encoder: &mut gfx::Encoder<R, C>,
+        config: &ShaderConfig,
+    ) -> error::Result<()>
+    where
+        F: gfx::Factory<R>,
+        C: gfx::CommandBuffer<R>,
+    {
+        let passes = create_passes(factory, config, &self.vert_src_buf, self.image_format)?;
+        let buffers = create_buffers(factory, encoder, &passes, self.width, self.height)?;
+        let cube = create_cube(factory, encoder, &passes)?;
+        let textures = load_textures(factory, encoder, config)?;
+        self.passes = passes;
+        self.buffers = buffers;
+        self.cube = cube;
+        self.textures = textures;
+
+        // Reset uniforms.
+        self.data.i_global_time = 0.0;
+        self.data.i_time = 0.0;
+        self.data.i_time_delta = 0.0;
+        self.data.i_frame_rate = 0.0;
+        self.data.i_mouse = [0.0; 4];
+        self.data.i_frame = -1;
+        self.data.i_sample_rate = config.sample_rate as f32;
+
+        Ok(())
+    }
+
+    // Changes the output resolution. Buffers always match it, so they are recreated (cleared).
+    pub fn resize<F, C>(
+        &mut self,
+        factory: &mut F,
+        encoder: &mut gfx::Encoder<R, C>,
+        width: f32,
+        height: f32,
+    ) -> error::Result<()>
+    where
+        F: gfx::Factory<R>,
+        C: gfx::CommandBuffer<R>,
+    {
+        self.buffers = create_buffers(factory, encoder, &self.passes, width, height)?;
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    // True if the shader has buffer (or Cube A) passes, whose output depends on every previous
+    // frame.
+    pub fn has_buffers(&self) -> bool {
+        self.buffers.iter().any(Option::is_some) || self.cube.is_some()
+    }
+
+    // Sets iTime and iTimeDelta (and so iFrameRate) for the next frame.
+    pub fn set_time(&mut self, time: f32, time_delta: f32) {
+        self.data.i_global_time = time;
+        self.data.i_time = time;
+        self.data.i_time_delta = time_delta;
+        self.data.i_frame_rate = if time_delta > 0.0 {
+            1.0 / time_delta
+        } else {
+            0.0
+        };
+    }
+
+    // Makes the next rendered frame have the given iFrame.
+    pub fn set_frame(&mut self, frame: i32) {
+        self.data.i_frame = frame - 1;
+    }
+
+    pub fn set_mouse(&mut self, mouse: [f32; 4]) {
+        self.data.i_mouse = mouse;
+    }
+
+    // Updates the keyboard texture for the next frame.
+    pub fn set_keyboard(&mut self, keyboard: &Keyboard) {
+        self.keyboard.set(keyboard.texels());
+    }
+
+    // Fixes iDate (e.g. for reproducible output), or goes back to the current date if None.
+    pub fn set_date(&mut self, date: Option<[f32; 4]>) {
+        self.date = date;
+    }
+
+    // Renders the next frame, drawing each pass in order with the image pass drawing to `target`.
+    pub fn render<C>(
+        &mut self,
+        encoder: &mut gfx::Encoder<R, C>,
+        target: &gfx::handle::RawRenderTargetView<R>,
+    ) where
+        C: gfx::CommandBuffer<R>,
+    {
+        let Renderer {
+            ref passes,
+            ref mut buffers,
+            ref mut cube,
+            ref textures,
+            ref slice,
+            ref empty,
+            ref mut keyboard,
+            ref mut data,
+            width,
+            height,
+            date,
+            ..
+        } = *self;
+
+        // Date.
+        data.i_date = date.unwrap_or_else(current_date);
+
+        // Frame.
+        data.i_frame += 1;
+
+        // Keyboard.
+        keyboard.upload(encoder);
+
+        for pass in passes {
+            let channel = |n: usize| {
+                let keyboard = &keyboard.view;
+                channel_texture(
+                    pass.inputs[n],
+                    textures,
+                    empty,
+                    keyboard,
+                    buffers,
+                    cube.as_ref(),
+                    [width, height, 1.0],
+                )
+            };
+            let (view0, resolution0) = channel(0);
+            let (view1, resolution1) = channel(1);
+            let (view2, resolution2) = channel(2);
+            let (view3, resolution3) = channel(3);
+            data.i_channel0 = (view0, pass.samplers[0].clone());
+            data.i_channel1 = (view1, pass.samplers[1].clone());
+            data.i_channel2 = (view2, pass.samplers[2].clone());
+            data.i_channel3 = (view3, pass.samplers[3].clone());
+            data.i_channel_resolution0 = resolution0;
+            data.i_channel_resolution1 = resolution1;
+            data.i_channel_resolution2 = resolution2;
+            data.i_channel_resolution3 = resolution3;
+
+            // Resolution.
+            data.i_resolution = [width, height, width / height];
+
+            match pass.id {
+                PassId::Buffer(id) => {
+                    let buffer = buffers[id.index()].as_mut().unwrap();
+                    data.frag_color = buffer.back().raw().clone();
+                    encoder.draw(slice, &pass.pso, data);
+                    buffer.swap();
+                }
+                PassId::CubeA => {
+                    let cube = cube.as_mut().unwrap();
+                    let size = CUBE_SIZE as f32;
+                    data.i_resolution = [size, size, 1.0];
+                    for (face, target) in cube.back().iter().enumerate() {
+                        data.cube_face = face as i32;
+                        data.frag_color = target.raw().clone();
+                        encoder.draw(slice, &pass.pso, data);
+                    }
+                    cube.swap();
+                }
+                PassId::Image => {
+                    data.frag_color = target.clone();
+                    encoder.draw(slice, &pass.pso, data);
+                }
+            }
+        }
+    }
+}
@@ -2,14 +2,16 @@ use argvalues::ArgValues;
 use download;
 use error;
 use gfx;
-use gfx::texture;
-use gfx::texture::FilterMethod;
-use gfx::Factory;
-use loader;
+use gfx::format::Formatted;
+use gfx::memory::Typed;
+use keyboard::{self, Keyboard};
+use mouse::{Mouse, MouseMode};
+use overlay::Overlay;
+use renderer::{ColorFormat, Renderer};
 
 use old_school_gfx_glutin_ext::*;
 
-use gfx::{traits::FactoryExt, Device};
+use gfx::Device;
 use glutin::{
     event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -19,96 +21,60 @@ use glutin::{
 use glutin::event::{ElementState, MouseButton};
 
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
-use std::path::Path;
+use std::ffi::OsString;
 use std::sync::mpsc::{channel, TryRecvError};
 
 use std::time::{Duration, Instant};
 
-pub enum TextureId {
-    Zero,
-    One,
-    Two,
-    Three,
-}
-
-type ColorFormat = gfx::format::Rgba8;
 type DepthFormat = gfx::format::DepthStencil;
 
-gfx_defines! {
-    vertex Vertex {
-        pos: [f32; 2] = "position",
-    }
-
-    pipeline pipe {
-        // Vertex buffer.
-        vbuf: gfx::VertexBuffer<Vertex> = (),
-
-        // Uniforms.
-        i_global_time: gfx::Global<f32> = "iGlobalTime",
-        i_time: gfx::Global<f32> = "iTime",
-        i_resolution: gfx::Global<[f32; 3]> = "iResolution",
-        i_mouse: gfx::Global<[f32; 4]> = "iMouse",
-        i_frame: gfx::Global<i32> = "iFrame",
-        i_channel0: gfx::TextureSampler<[f32; 4]> = "iChannel0",
-        i_channel1: gfx::TextureSampler<[f32; 4]> = "iChannel1",
-        i_channel2: gfx::TextureSampler<[f32; 4]> = "iChannel2",
-        i_channel3: gfx::TextureSampler<[f32; 4]> = "iChannel3",
-
-        // Output color.
-        frag_color: gfx::RenderTarget<ColorFormat> = "fragColor",
-    }
-}
-
-const SCREEN: [Vertex; 4] = [
-    Vertex { pos: [1.0, 1.0] },   // Top right.
-    Vertex { pos: [-1.0, 1.0] },  // Top left.
-    Vertex { pos: [-1.0, -1.0] }, // Bottom left.
-    Vertex { pos: [1.0, -1.0] },  // Bottom right.
-];
-
-const SCREEN_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
-
 const CLEAR_COLOR: [f32; 4] = [1.0; 4];
 
-pub fn run(av: ArgValues) -> error::Result<()> {
-    let (mut width, mut height) = (av.width, av.height);
+pub fn run(mut av: ArgValues) -> error::Result<()> {
+    let (width, mut height) = (av.width, av.height);
 
-    // Load vertex and fragment shaders into byte buffers.
-    let vert_src_buf = loader::load_vertex_shader();
-    let frag_src_buf = match av.getid {
-        Some(ref id) => {
-            let (_, shadercode) = download::download(id)?;
-
-            // Don't run default shader if downloading (with no --run flag).
-            if av.getid.is_some() && !av.andrun {
-                return Ok(());
-            }
+    // Downloaded shaders are saved as a project, which then runs like any other.
+    if let Some(id) = av.getid.clone() {
+        let manifest_path = av.downloader().download(&id)?;
 
-            if av.andrun {
-                loader::format_shader_src(&shadercode)
-            } else {
-                loader::load_fragment_shader(&av)?
-            }
+        // Don't run default shader if downloading (with no --run flag).
+        if !av.andrun {
+            return Ok(());
         }
-        None => loader::load_fragment_shader(&av)?,
-    };
+        av.shaderpath = Some(manifest_path);
+    }
+    if let Some(ref id) = av.runid {
+        av.shaderpath = Some(download::load_cached(id)?);
+    }
+
+    // Load shader sources.
+    let config = av.shader_config()?;
 
     let (tx, rx) = channel();
     let mut watcher = watcher(tx, Duration::from_millis(250)).expect("Could not initialise notify");
 
-    let shader_basename = av.shaderpath.as_ref().map(|path| {
-        let path = Path::new(&path);
-        watcher
-            .watch(path.parent().unwrap(), RecursiveMode::NonRecursive)
-            .expect("Could not register inotify watch");
-        path.file_name().unwrap().to_os_string()
-    });
+    // Watch the image shader along with any common and buffer shaders (or the project manifest and
+    // its sources).
+    let shader_paths = av.shader_paths()?;
+    let mut watched_dirs = Vec::new();
+    let mut shader_basenames: Vec<OsString> = Vec::new();
+    for path in &shader_paths {
+        let dir = path.parent().unwrap().to_path_buf();
+        if !watched_dirs.contains(&dir) {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .expect("Could not register inotify watch");
+            watched_dirs.push(dir);
+        }
+        shader_basenames.push(path.file_name().unwrap().to_os_string());
+    }
 
     let event_loop = EventLoop::new();
 
     let shader_name = av
         .getid
         .as_ref()
+        .or(av.runid.as_ref())
         .or(av.shaderpath.as_ref())
         .or(av.examplename.as_ref());
     let shader_title = shader_name.map(|name| format!("{} - shadertoy-rs", name));
@@ -126,7 +92,7 @@ pub fn run(av: ArgValues) -> error::Result<()> {
         .with_title(window_title.unwrap())
         .with_inner_size(glutin::dpi::PhysicalSize::new(width, height));
 
-    let (window, mut device, mut factory, main_color, mut main_depth) =
+    let (window, mut device, mut factory, mut main_color, mut main_depth) =
         glutin::ContextBuilder::new()
             .with_gfx_color_depth::<ColorFormat, DepthFormat>()
             .build_windowed(window_config, &event_loop)
@@ -143,73 +109,29 @@ pub fn run(av: ArgValues) -> error::Result<()> {
 
     let mut encoder = gfx::Encoder::from(factory.create_command_buffer());
 
-    let mut pso = factory
-        .create_pipeline_simple(&vert_src_buf, &frag_src_buf, pipe::new())
-        .unwrap();
-
-    let (vertex_buffer, slice) =
-        factory.create_vertex_buffer_with_slice(&SCREEN, &SCREEN_INDICES[..]);
-
-    // Load textures.
-    let texture0 = loader::load_texture(&TextureId::Zero, &av.texture0path, &mut factory)?;
-    let texture1 = loader::load_texture(&TextureId::One, &av.texture1path, &mut factory)?;
-    let texture2 = loader::load_texture(&TextureId::Two, &av.texture2path, &mut factory)?;
-    let texture3 = loader::load_texture(&TextureId::Three, &av.texture3path, &mut factory)?;
-
-    let needs_mipmap =
-        |mode: FilterMethod| mode != FilterMethod::Scale && mode != FilterMethod::Bilinear;
-
-    // Generate mipmaps if needed.
-    if needs_mipmap(av.filter0) {
-        encoder.generate_mipmap(&texture0)
-    };
-    if needs_mipmap(av.filter1) {
-        encoder.generate_mipmap(&texture1)
-    };
-    if needs_mipmap(av.filter2) {
-        encoder.generate_mipmap(&texture2)
-    };
-    if needs_mipmap(av.filter3) {
-        encoder.generate_mipmap(&texture3)
-    };
-
-    let mut data = pipe::Data {
-        vbuf: vertex_buffer,
-
-        i_global_time: 0.0,
-        i_time: 0.0,
-        i_resolution: [width, height, width / height],
-        i_mouse: [0.0; 4],
-        i_frame: -1,
-
-        i_channel0: (
-            texture0,
-            factory.create_sampler(texture::SamplerInfo::new(av.filter0, av.wrap0)),
-        ),
-        i_channel1: (
-            texture1,
-            factory.create_sampler(texture::SamplerInfo::new(av.filter1, av.wrap1)),
-        ),
-        i_channel2: (
-            texture2,
-            factory.create_sampler(texture::SamplerInfo::new(av.filter2, av.wrap2)),
-        ),
-        i_channel3: (
-            texture3,
-            factory.create_sampler(texture::SamplerInfo::new(av.filter3, av.wrap3)),
-        ),
-
-        frag_color: main_color,
-    };
+    let mut renderer = Renderer::new(
+        &mut factory,
+        &mut encoder,
+        &config,
+        ColorFormat::get_format(),
+        width,
+        height,
+        main_color.raw(),
+    )?;
 
-    let mut last_mouse = ElementState::Released;
-    let mut current_mouse = ElementState::Released;
+    // Shows why the last reload failed, until one succeeds.
+    let mut overlay = Overlay::new(&mut factory, &main_color)?;
 
-    let (mut mx, mut my) = (0.0, 0.0);
+    let mut keys = Keyboard::new();
 
-    let mut xyzw = [0.0; 4];
+    let mut mouse = Mouse::new(if av.legacy_mouse {
+        MouseMode::Legacy
+    } else {
+        MouseMode::Modern
+    });
 
     let mut start_time = Instant::now();
+    let mut last_elapsed_sec = 0.0;
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
         let mut shader_modified = false;
@@ -229,46 +151,67 @@ pub fn run(av: ArgValues) -> error::Result<()> {
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::F5),
+                            virtual_keycode: Some(key),
+                            state,
                             ..
                         },
                     ..
-                } => shader_modified = true,
+                } => {
+                    if key == VirtualKeyCode::F5 && state == ElementState::Pressed {
+                        shader_modified = true;
+                    }
+                    if let Some(keycode) = keyboard::js_keycode(key) {
+                        match state {
+                            ElementState::Pressed => keys.press(keycode),
+                            ElementState::Released => keys.release(keycode),
+                        }
+                    }
+                }
 
-                WindowEvent::Resized(size) => {
-                    window.update_gfx(&mut data.frag_color, &mut main_depth);
+                WindowEvent::Focused(false) => keys.release_all(),
+
+                // Sizes are in physical pixels, which is what the shader renders at. Moving to a
+                // monitor with a different scale factor changes the physical size too.
+                WindowEvent::Resized(size)
+                | WindowEvent::ScaleFactorChanged {
+                    new_inner_size: &mut size,
+                    ..
+                } => {
+                    window.update_gfx(&mut main_color, &mut main_depth);
                     window.resize(size);
 
-                    width = size.width as f32;
+                    let width = size.width as f32;
                     height = size.height as f32;
-                }
 
-                WindowEvent::CursorMoved {
-                    position: cursor_position,
-                    ..
-                } => {
-                    mx = cursor_position.x as f32;
-                    my = height - cursor_position.y as f32; // Flip y-axis.
+                    if let Err(e) = renderer.resize(&mut factory, &mut encoder, width, height) {
+                        println!("Failed to resize buffers: {}", e);
+                    }
                 }
 
-                WindowEvent::MouseInput { state, button, .. } => {
-                    last_mouse = current_mouse;
-                    if state == ElementState::Pressed && button == MouseButton::Left {
-                        current_mouse = ElementState::Pressed;
-                    } else {
-                        current_mouse = ElementState::Released;
-                    }
+                // The cursor is also in physical pixels, so it lines up with fragCoord whatever
+                // the scale factor.
+                WindowEvent::CursorMoved { position, .. } => {
+                    // Flip y-axis.
+                    mouse.move_to(position.x as f32, height - position.y as f32);
                 }
 
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => match state {
+                    ElementState::Pressed => mouse.press(),
+                    ElementState::Released => mouse.release(),
+                },
+
                 _ => (),
             }
         }
         // Notify handling.
-        shader_modified |= match shader_basename.is_some() {
-            false => false,
-            true => {
+        shader_modified |= match shader_basenames.is_empty() {
+            true => false,
+            false => {
                 let mut have_events = false;
-                let basename = &shader_basename;
 
                 loop {
                     match rx.try_recv() {
@@ -279,7 +222,9 @@ pub fn run(av: ArgValues) -> error::Result<()> {
                         Ok(DebouncedEvent::Create(ref path))
                         | Ok(DebouncedEvent::Write(ref path))
                         | Ok(DebouncedEvent::Rename(_, ref path))
-                            if path.ends_with(basename.as_ref().unwrap().as_os_str()) =>
+                            if shader_basenames
+                                .iter()
+                                .any(|basename| path.ends_with(basename)) =>
                         {
                             have_events = true
                         }
@@ -299,67 +244,44 @@ pub fn run(av: ArgValues) -> error::Result<()> {
             }
         };
 
-        // Attempt to reload the shader if it has been modified. In the event that the new version
-        // does not load/compile properly, the old version will continue running.
+        // Attempt to reload the shaders if any have been modified. In the event that a new version
+        // does not load/compile properly, the old versions will continue running, dimmed behind
+        // the errors.
         if shader_modified {
-            // Reload fragment shader into byte buffer.
-            match loader::load_fragment_shader(&av) {
-                Ok(frag_src_res) => {
-                    let frag_src_buf = frag_src_res.as_slice();
-
-                    // Recreate pipeline.
-                    match factory.create_pipeline_simple(&vert_src_buf, frag_src_buf, pipe::new()) {
-                        Ok(new_pso) => {
-                            pso = new_pso;
-
-                            // Reset uniforms.
-                            data.i_global_time = 0.0;
-                            data.i_time = 0.0;
-                            data.i_resolution = [width, height, width / height];
-                            data.i_mouse = [0.0; 4];
-                            data.i_frame = -1;
-
-                            start_time = Instant::now();
-                        }
-
-                        Err(e) => println!("Failed to create pipeline: {:?}", e),
+            let reloaded = av
+                .shader_config()
+                .and_then(|config| renderer.reload(&mut factory, &mut encoder, &config));
+            match reloaded {
+                Ok(()) => {
+                    start_time = Instant::now();
+                    last_elapsed_sec = 0.0;
+                    overlay.hide();
+                }
+                Err(e) => {
+                    let message = format!("Failed to reload shaders: {}", e);
+                    println!("{}", message);
+                    if let Err(e) = overlay.show(&mut factory, &message) {
+                        println!("Failed to show error overlay: {}", e);
                     }
                 }
-
-                Err(e) => println!("Failed to load fragment shader: {:?}", e),
             }
         }
 
-        // Mouse.
-        if current_mouse == ElementState::Pressed {
-            xyzw[0] = mx;
-            xyzw[1] = my;
-            if last_mouse == ElementState::Released {
-                xyzw[2] = mx;
-                xyzw[3] = my;
-            }
-        } else {
-            xyzw[2] = 0.0;
-            xyzw[3] = 0.0;
-        }
-        data.i_mouse = xyzw;
+        renderer.set_mouse(mouse.uniform());
+        renderer.set_keyboard(&keys);
+        mouse.end_frame();
+        keys.end_frame();
 
         // Elapsed time.
         let elapsed = start_time.elapsed();
         let elapsed_ms = (elapsed.as_secs() * 1000) + u64::from(elapsed.subsec_millis());
         let elapsed_sec = (elapsed_ms as f32) / 1000.0;
-        data.i_global_time = elapsed_sec;
-        data.i_time = elapsed_sec;
-
-        // Resolution.
-        data.i_resolution = [width, height, width / height];
-
-        // Frame.
-        data.i_frame += 1;
+        renderer.set_time(elapsed_sec, elapsed_sec - last_elapsed_sec);
+        last_elapsed_sec = elapsed_sec;
 
-        // Draw.
-        encoder.clear(&data.frag_color, CLEAR_COLOR);
-        encoder.draw(&slice, &pso, &data);
+        encoder.clear(&main_color, CLEAR_COLOR);
+        renderer.render(&mut encoder, main_color.raw());
+        overlay.render(&mut encoder, &main_color, window.window().scale_factor());
         encoder.flush(&mut device);
         window.swap_buffers().unwrap();
         device.cleanup();
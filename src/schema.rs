@@ -0,0 +1,315 @@
+use error::{self, MalformedResponseError, ShadertoyApiError};
+use project::{Filter, Wrap};
+
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+use serde_json::{self, Value};
+
+use std::fmt;
+
+// The JSON of a shader, as returned by both the API and the site's own endpoint. The two differ in
+// small ways (e.g. "src" vs "filepath", numeric vs string ids), so only what the downloader needs
+// is parsed, and anything optional has a default.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Shader {
+    pub info: Info,
+
+    #[serde(rename = "renderpass")]
+    pub renderpasses: Vec<RenderPass>,
+}
+
+impl Shader {
+    // Parses a shader's JSON, failing cleanly if it isn't what shadertoy.com returns.
+    pub fn from_json(json: &Value) -> error::Result<Shader> {
+        parse(json, "shader")
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Info {
+    pub id: String,
+    pub name: String,
+
+    #[serde(default)]
+    pub username: String,
+
+    #[serde(default)]
+    pub description: String,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    // Seconds since the epoch the shader was published at.
+    #[serde(default, deserialize_with = "lenient")]
+    pub date: Option<Id>,
+
+    // 0 if the shader is private, 1 if public, 2 if unlisted and 3 if public and available
+    // through the API.
+    #[serde(default, deserialize_with = "lenient")]
+    pub published: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RenderPass {
+    #[serde(default)]
+    pub inputs: Vec<Input>,
+
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+
+    #[serde(default)]
+    pub code: String,
+
+    #[serde(default)]
+    pub name: String,
+
+    // "image", "buffer", "common", "cubemap" or "sound".
+    #[serde(default, rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Input {
+    #[serde(default, deserialize_with = "lenient")]
+    pub id: Option<Id>,
+
+    // URL of the media file (or the placeholder image of a buffer or Cube A).
+    #[serde(default, alias = "filepath")]
+    pub src: Option<String>,
+
+    // "texture", "buffer", "cubemap", "volume", "keyboard", "music", etc.
+    #[serde(default, alias = "type")]
+    pub ctype: Option<String>,
+
+    #[serde(default, deserialize_with = "lenient")]
+    pub channel: Option<u64>,
+
+    #[serde(default)]
+    pub sampler: Sampler,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Output {
+    #[serde(default, deserialize_with = "lenient")]
+    pub id: Option<Id>,
+}
+
+// Unknown values are treated as missing, so they fall back to the defaults for the input.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Sampler {
+    #[serde(default, deserialize_with = "lenient")]
+    pub filter: Option<Filter>,
+
+    #[serde(default, deserialize_with = "lenient")]
+    pub wrap: Option<Wrap>,
+
+    #[serde(default, deserialize_with = "flag")]
+    pub vflip: Option<bool>,
+
+    #[serde(default, deserialize_with = "flag")]
+    pub srgb: Option<bool>,
+}
+
+// Ids are strings on the site, but numbers in the API.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Id {
+    String(String),
+    Number(u64),
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Id::String(ref id) => write!(f, "{}", id),
+            Id::Number(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+// Response of the API for a shader.
+#[derive(Deserialize)]
+pub struct ShaderResponse {
+    #[serde(rename = "Shader")]
+    pub shader: Value,
+}
+
+// Response of the API for a query, without any results if nothing matches.
+#[derive(Deserialize)]
+pub struct QueryResponse {
+    #[serde(rename = "Results", default)]
+    pub results: Vec<String>,
+}
+
+// Parses a response, failing with a MalformedResponseError naming what it should have been.
+pub fn parse<T: DeserializeOwned>(json: &Value, what: &str) -> error::Result<T> {
+    T::deserialize(json)
+        .map_err(|err| MalformedResponseError::new(format!("invalid {}: {}", what, err)).into())
+}
+
+// Parses a response of the /api/v1 endpoints, which are {"Error": "..."} if the request failed.
+pub fn parse_api<T: DeserializeOwned>(json: &Value, what: &str) -> error::Result<T> {
+    if let Some(error) = json.get("Error") {
+        let error = error.as_str().unwrap_or("unknown").to_string();
+        return Err(ShadertoyApiError::new(error).into());
+    }
+    parse(json, what)
+}
+
+// Deserializes a value, or None if it isn't valid, instead of rejecting the whole shader.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
+
+// Sampler flags are usually the strings "true" and "false", but can be booleans.
+fn flag<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Bool(flag) => Ok(Some(flag)),
+        Value::String(ref flag) if flag == "true" => Ok(Some(true)),
+        Value::String(ref flag) if flag == "false" => Ok(Some(false)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn parses_shaders() {
+        let shader = Shader::from_json(&json(
+            r#"{
+                "info": {"id": "XdXGzn", "name": "Seascape", "date": "1430000000", "published": 3},
+                "renderpass": [{
+                    "inputs": [{"id": 17, "filepath": "/media/a/noise.png", "type": "texture",
+                                "channel": 0}],
+                    "outputs": [{"id": "4dfGRr"}],
+                    "code": "void mainImage() {}",
+                    "name": "Image",
+                    "type": "image"
+                }]
+            }"#,
+        ))
+        .unwrap();
+        assert_eq!(shader.info.id, "XdXGzn");
+        assert_eq!(shader.info.username, "");
+        assert_eq!(shader.info.date, Some(Id::String("1430000000".to_string())));
+        assert_eq!(shader.info.published, Some(3));
+
+        let pass = &shader.renderpasses[0];
+        assert_eq!(pass.kind, "image");
+        assert_eq!(pass.inputs[0].id, Some(Id::Number(17)));
+        assert_eq!(pass.inputs[0].src.as_deref(), Some("/media/a/noise.png"));
+        assert_eq!(pass.inputs[0].ctype.as_deref(), Some("texture"));
+        assert_eq!(pass.inputs[0].channel, Some(0));
+        assert_eq!(pass.outputs[0].id, Some(Id::String("4dfGRr".to_string())));
+        assert_eq!(Id::Number(17).to_string(), "17");
+    }
+
+    #[test]
+    fn ignores_invalid_values() {
+        let input: Input = parse(
+            &json(
+                r#"{
+                    "id": [1], "src": "/a.png", "ctype": "texture", "channel": "zero",
+                    "sampler": {"filter": "bicubic", "wrap": 3, "vflip": "yes", "srgb": "false"}
+                }"#,
+            ),
+            "input",
+        )
+        .unwrap();
+        assert_eq!(input.id, None);
+        assert_eq!(input.channel, None);
+        assert!(input.sampler.filter.is_none());
+        assert!(input.sampler.wrap.is_none());
+        assert_eq!(input.sampler.vflip, None);
+        assert_eq!(input.sampler.srgb, Some(false));
+
+        let info: Info = parse(
+            &json(r#"{"id": "x", "name": "y", "published": "no"}"#),
+            "info",
+        )
+        .unwrap();
+        assert_eq!(info.published, None);
+        assert_eq!(info.date, None);
+    }
+
+    #[test]
+    fn parses_flags() {
+        let sampler =
+            |json_flags: &str| -> Sampler { parse(&json(json_flags), "sampler").unwrap() };
+        let flags = |sampler: Sampler| (sampler.vflip, sampler.srgb);
+        assert_eq!(
+            flags(sampler(r#"{"vflip": "true", "srgb": "false"}"#)),
+            (Some(true), Some(false))
+        );
+        assert_eq!(
+            flags(sampler(r#"{"vflip": false, "srgb": true}"#)),
+            (Some(false), Some(true))
+        );
+        assert_eq!(
+            flags(sampler(r#"{"vflip": 1, "srgb": null}"#)),
+            (None, None)
+        );
+        assert_eq!(flags(sampler("{}")), (None, None));
+
+        let sampler = sampler(r#"{"filter": "nearest", "wrap": "clamp"}"#);
+        assert!(matches!(sampler.filter, Some(Filter::Nearest)));
+        assert!(matches!(sampler.wrap, Some(Wrap::Clamp)));
+    }
+
+    #[test]
+    fn rejects_malformed_shaders() {
+        let err =
+            Shader::from_json(&json(r#"{"info": {"id": "x"}, "renderpass": []}"#)).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("invalid shader: missing field `name`"),
+            "{}",
+            err
+        );
+
+        let err = Shader::from_json(&json("[]")).unwrap_err();
+        assert!(err.to_string().contains("invalid shader"), "{}", err);
+    }
+
+    #[test]
+    fn parses_api_responses() {
+        let response: ShaderResponse =
+            parse_api(&json(r#"{"Shader": {"info": {}}}"#), "shader response").unwrap();
+        assert_eq!(response.shader, json(r#"{"info": {}}"#));
+
+        let result = parse_api::<ShaderResponse>(&json(r#"{"shader": {}}"#), "shader response");
+        let err = result.err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("invalid shader response: missing field `Shader`"),
+            "{}",
+            err
+        );
+
+        let result = parse_api::<ShaderResponse>(&json(r#"{"Error": "Shader not found"}"#), "x");
+        let err = result.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Shadertoy API request failed: Shader not found"
+        );
+
+        let response: QueryResponse =
+            parse_api(&json(r#"{"Shaders": 2, "Results": ["a", "b"]}"#), "query").unwrap();
+        assert_eq!(response.results, ["a", "b"]);
+        let response: QueryResponse = parse_api(&json(r#"{"Shaders": 0}"#), "query").unwrap();
+        assert!(response.results.is_empty());
+    }
+}
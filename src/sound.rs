@@ -0,0 +1,240 @@
+use argvalues::ArgValues;
+use error::{self, SaveSoundError};
+use gfx;
+use gfx::texture::{self, FilterMethod, SamplerInfo, WrapMode};
+use gfx::traits::FactoryExt;
+use gfx::Factory;
+use headless::Headless;
+use hound;
+use loader;
+use pass::ChannelInput;
+use project::{self, Project};
+use renderer::{self, TextureKind};
+
+// Samples are rendered in square blocks, one stereo sample per pixel. Must match the width used
+// in loader::SOUND_SUFFIX.
+const BLOCK_SIZE: u16 = 512;
+
+type SoundFormat = gfx::format::Rgba32F;
+
+gfx_defines! {
+    pipeline sound_pipe {
+        // Vertex buffer.
+        vbuf: gfx::VertexBuffer<renderer::Vertex> = (),
+
+        // Uniforms.
+        i_resolution: gfx::Global<[f32; 3]> = "iResolution",
+        i_sample_rate: gfx::Global<f32> = "iSampleRate",
+        i_block_offset: gfx::Global<f32> = "iBlockOffset",
+        block_start: gfx::Global<i32> = "_blockStart",
+        i_date: gfx::Global<[f32; 4]> = "iDate",
+        i_channel0: gfx::TextureSampler<[f32; 4]> = "iChannel0",
+        i_channel1: gfx::TextureSampler<[f32; 4]> = "iChannel1",
+        i_channel2: gfx::TextureSampler<[f32; 4]> = "iChannel2",
+        i_channel3: gfx::TextureSampler<[f32; 4]> = "iChannel3",
+        i_channel_resolution0: gfx::Global<[f32; 3]> = "iChannelResolution0",
+        i_channel_resolution1: gfx::Global<[f32; 3]> = "iChannelResolution1",
+        i_channel_resolution2: gfx::Global<[f32; 3]> = "iChannelResolution2",
+        i_channel_resolution3: gfx::Global<[f32; 3]> = "iChannelResolution3",
+        i_channel_time0: gfx::Global<f32> = "iChannelTime0",
+        i_channel_time1: gfx::Global<f32> = "iChannelTime1",
+        i_channel_time2: gfx::Global<f32> = "iChannelTime2",
+        i_channel_time3: gfx::Global<f32> = "iChannelTime3",
+
+        // Output samples, with the left and right channels in red and green.
+        frag_color: gfx::RenderTarget<SoundFormat> = "fragColor",
+    }
+}
+
+// Evaluates the sound shader's mainSound on the GPU, returning interleaved stereo samples.
+pub fn render_samples(av: &ArgValues, soundpath: &str) -> error::Result<Vec<f32>> {
+    let mut headless = Headless::new()?;
+    let mut encoder = headless.create_encoder();
+
+    let vert_src_buf = loader::load_vertex_shader();
+    let project = if project::is_manifest(soundpath) {
+        Some(Project::load(soundpath)?)
+    } else {
+        None
+    };
+    let (common_src, sound_src, common_name, sound_name) = if let Some(ref project) = project {
+        let (common_src, sound_src) = project.sound_srcs()?;
+        let manifest = &project.manifest;
+        let common_name = manifest
+            .common
+            .as_ref()
+            .map(|common| project.source_path(common));
+        let sound_name = manifest
+            .sound
+            .as_ref()
+            .map(|sound| project.source_path(&sound.source));
+        (common_src, sound_src, common_name, sound_name)
+    } else {
+        let sound_name = Some(soundpath.to_string());
+        (
+            av.common_src()?,
+            loader::read_shader_file(soundpath)?,
+            av.commonpath.clone(),
+            sound_name,
+        )
+    };
+    // Sound passes can only read textures. The sound pass of a manifest reads what its channels
+    // list; otherwise iChannelN reads the n-th texture from the command line.
+    let (textures, channels) = match project {
+        Some(ref project) => {
+            let mut textures = Vec::new();
+            let channels = project.sound_channels(&mut textures)?;
+            (textures, channels)
+        }
+        None => {
+            let samplers = av.samplers();
+            let channels = [0, 1, 2, 3].map(|n| (ChannelInput::Texture(n), Some(samplers[n])));
+            (av.textures(), channels)
+        }
+    };
+    let channel_kinds = channels.map(|(input, _)| match input {
+        ChannelInput::Texture(n) => textures[n].kind(),
+        _ => TextureKind::D2,
+    });
+
+    let sound_src = loader::format_sound_shader_src(
+        channel_kinds,
+        common_name.as_deref().unwrap_or("Common"),
+        &common_src,
+        sound_name.as_deref().unwrap_or("Sound"),
+        &sound_src,
+    );
+    let pso = headless
+        .factory
+        .create_pipeline_simple(&vert_src_buf, sound_src.as_bytes(), sound_pipe::new())
+        .map_err(|err| error::CreatePipelineError::new("Sound", sound_src.explain(&err)))?;
+
+    let (vertex_buffer, slice) = headless
+        .factory
+        .create_vertex_buffer_with_slice(&renderer::SCREEN, &renderer::SCREEN_INDICES[..]);
+    let target = headless.create_readback_target::<SoundFormat>(BLOCK_SIZE, BLOCK_SIZE)?;
+
+    let factory = &mut headless.factory;
+    let textures = textures
+        .iter()
+        .map(|source| {
+            let texture = loader::load_texture(source, factory)?;
+            encoder.generate_mipmap(&texture.0);
+            Ok(texture)
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+
+    // Empty channels read a single transparent black texel, with a resolution of zero.
+    let kind = texture::Kind::D2(1, 1, texture::AaMode::Single);
+    let (_, empty) = factory.create_texture_immutable_u8::<gfx::format::Rgba8>(
+        kind,
+        texture::Mipmap::Provided,
+        &[&[0; 4]],
+    )?;
+    let default_sampler = SamplerInfo::new(FilterMethod::Mipmap, WrapMode::Tile);
+    let mut channel = |n: usize| {
+        let (input, sampler) = channels[n];
+        let texture = match input {
+            ChannelInput::Texture(index) => textures[index].clone(),
+            _ => (empty.clone(), [0.0; 3]),
+        };
+        let sampler = factory.create_sampler(sampler.unwrap_or(default_sampler));
+        ((texture.0, sampler), texture.1)
+    };
+    let (channel0, resolution0) = channel(0);
+    let (channel1, resolution1) = channel(1);
+    let (channel2, resolution2) = channel(2);
+    let (channel3, resolution3) = channel(3);
+
+    let mut data = sound_pipe::Data {
+        vbuf: vertex_buffer,
+
+        i_resolution: [f32::from(BLOCK_SIZE), f32::from(BLOCK_SIZE), 1.0],
+        i_sample_rate: av.sample_rate as f32,
+        i_block_offset: 0.0,
+        block_start: 0,
+        i_date: renderer::current_date(),
+        i_channel0: channel0,
+        i_channel1: channel1,
+        i_channel2: channel2,
+        i_channel3: channel3,
+        i_channel_resolution0: resolution0,
+        i_channel_resolution1: resolution1,
+        i_channel_resolution2: resolution2,
+        i_channel_resolution3: resolution3,
+        i_channel_time0: 0.0,
+        i_channel_time1: 0.0,
+        i_channel_time2: 0.0,
+        i_channel_time3: 0.0,
+
+        frag_color: target.target.clone(),
+    };
+
+    let num_samples = (av.sound_duration * av.sample_rate as f32) as usize;
+    let block_samples = usize::from(BLOCK_SIZE) * usize::from(BLOCK_SIZE);
+
+    let mut samples = Vec::with_capacity(num_samples * 2);
+    for block_start in (0..num_samples).step_by(block_samples) {
+        data.i_block_offset = (block_start as f64 / f64::from(av.sample_rate)) as f32;
+        data.block_start = block_start as i32;
+        encoder.draw(&slice, &pso, &data);
+
+        let pixels: Vec<[f32; 4]> = headless.read_pixels(&mut encoder, &target)?;
+        let block_len = block_samples.min(num_samples - block_start);
+        for pixel in &pixels[..block_len] {
+            samples.push(pixel[0].clamp(-1.0, 1.0));
+            samples.push(pixel[1].clamp(-1.0, 1.0));
+        }
+    }
+
+    Ok(samples)
+}
+
+// Renders the sound shader to a 16-bit stereo WAV file, optionally playing it afterwards.
+pub fn render(av: &ArgValues, soundpath: &str) -> error::Result<()> {
+    let samples = render_samples(av, soundpath)?;
+
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: av.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let save_error = |err: hound::Error| SaveSoundError::new(&av.sound_outpath, err.to_string());
+    let mut writer = hound::WavWriter::create(&av.sound_outpath, spec).map_err(save_error)?;
+    for sample in &samples {
+        writer
+            .write_sample((sample * f32::from(i16::MAX)) as i16)
+            .map_err(save_error)?;
+    }
+    writer.finalize().map_err(save_error)?;
+
+    if av.play_sound {
+        play(&samples, av.sample_rate);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "playback")]
+fn play(samples: &[f32], sample_rate: u32) {
+    use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
+
+    // Playback is best-effort, since there may not be an audio device (e.g. on CI).
+    let (_stream, handle) = match OutputStream::try_default() {
+        Ok(output) => output,
+        Err(e) => return warn!("Skipping playback, no audio device: {}", e),
+    };
+    match Sink::try_new(&handle) {
+        Ok(sink) => {
+            sink.append(SamplesBuffer::new(2, sample_rate, samples.to_vec()));
+            sink.sleep_until_end();
+        }
+        Err(e) => warn!("Skipping playback: {}", e),
+    }
+}
+
+#[cfg(not(feature = "playback"))]
+fn play(_samples: &[f32], _sample_rate: u32) {
+    warn!("Skipping playback, shadertoy-rs was built without the \"playback\" feature");
+}
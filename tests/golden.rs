@@ -0,0 +1,190 @@
+// Golden-image regression tests. Every shader in examples/, plus the default shader, is rendered
+// offscreen with fixed inputs and compared against the reference images in tests/golden/.
+//
+// Environment variables:
+//   SHADERTOY_GOLDEN_TOLERANCE  Max per-channel difference (0-255) for a pixel to match. Default 2.
+//   SHADERTOY_UPDATE_GOLDEN     If set, (re)writes the reference images instead of comparing.
+//
+// On failure, the rendered image and a diff image (mismatched pixels in red) are written to
+// target/golden/.
+
+extern crate image;
+
+use image::{Rgba, RgbaImage};
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const DEFAULT_TOLERANCE: u8 = 2;
+
+// Fixed inputs, so that renders are reproducible.
+const WIDTH: &str = "160";
+const HEIGHT: &str = "90";
+const TIME: &str = "2.5";
+const FRAME: &str = "150";
+const MOUSE: &str = "80,45,0,0";
+const DATE: &str = "2017,0,1,0";
+
+struct Shader {
+    name: String,
+    path: PathBuf,
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn shaders() -> Vec<Shader> {
+    let mut shaders = vec![Shader {
+        name: "default".to_string(),
+        path: manifest_dir().join("shaders").join("default.frag"),
+    }];
+
+    let mut examples: Vec<PathBuf> = fs::read_dir(manifest_dir().join("examples"))
+        .expect("Could not read examples/")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "frag"))
+        .collect();
+    examples.sort();
+    for path in examples {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        shaders.push(Shader { name, path });
+    }
+
+    shaders
+}
+
+fn tolerance() -> u8 {
+    env::var("SHADERTOY_GOLDEN_TOLERANCE")
+        .map(|tolerance| {
+            tolerance
+                .parse()
+                .expect("SHADERTOY_GOLDEN_TOLERANCE must be 0-255")
+        })
+        .unwrap_or(DEFAULT_TOLERANCE)
+}
+
+// Renders the shader to `outpath` through Mesa's software rasterizer. Returns Err with the
+// process's stderr if rendering failed. Errors are only logged, so a render that failed is one
+// that didn't write `outpath`.
+fn render(shader: &Shader, outpath: &Path) -> Result<(), String> {
+    let _ = fs::remove_file(outpath);
+    let output = Command::new(env!("CARGO_BIN_EXE_shadertoy"))
+        .args(["-W", WIDTH, "-H", HEIGHT, "render"])
+        .arg(&shader.path)
+        .arg("--output")
+        .arg(outpath)
+        .args(["--time", TIME, "--frame", FRAME])
+        .args(["--mouse", MOUSE, "--date", DATE])
+        .env("LIBGL_ALWAYS_SOFTWARE", "1")
+        .env("GALLIUM_DRIVER", "llvmpipe")
+        .env("RUST_LOG", "error")
+        .output()
+        .expect("Could not run shadertoy");
+
+    if output.status.success() && outpath.exists() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+// Compares the images, returning the number of mismatched pixels and a diff image. Matching
+// pixels are drawn as a faded grayscale copy of the expected image.
+fn compare(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> (usize, RgbaImage) {
+    let mut mismatched = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    for (x, y, e) in expected.enumerate_pixels() {
+        let a = actual.get_pixel(x, y);
+        let max_diff = e
+            .data
+            .iter()
+            .zip(a.data.iter())
+            .map(|(&e, &a)| (i16::from(e) - i16::from(a)).unsigned_abs())
+            .max()
+            .unwrap();
+        let pixel = if max_diff > u16::from(tolerance) {
+            mismatched += 1;
+            [255, 0, 0, 255]
+        } else {
+            let gray = (e.data[..3].iter().map(|&c| u16::from(c)).sum::<u16>() / 6) as u8;
+            [gray, gray, gray, 255]
+        };
+        diff.put_pixel(x, y, Rgba { data: pixel });
+    }
+    (mismatched, diff)
+}
+
+#[test]
+fn golden_images() {
+    let golden_dir = manifest_dir().join("tests").join("golden");
+    let output_dir = manifest_dir().join("target").join("golden");
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let update = env::var_os("SHADERTOY_UPDATE_GOLDEN").is_some();
+    let tolerance = tolerance();
+
+    let mut failures = Vec::new();
+    for shader in shaders() {
+        let actual_path = output_dir.join(format!("{}.png", shader.name));
+        let golden_path = golden_dir.join(format!("{}.png", shader.name));
+        let diff_path = output_dir.join(format!("{}.diff.png", shader.name));
+        let _ = fs::remove_file(&diff_path);
+
+        if let Err(stderr) = render(&shader, &actual_path) {
+            // Without any OpenGL implementation there is nothing to test against.
+            if stderr.contains("Failed to create offscreen OpenGL context") {
+                eprintln!("Skipping golden image tests: {}", stderr.trim());
+                return;
+            }
+            failures.push(format!("{}: render failed:\n{}", shader.name, stderr));
+            continue;
+        }
+
+        if update {
+            fs::copy(&actual_path, &golden_path).unwrap();
+            continue;
+        }
+
+        let expected = match image::open(&golden_path) {
+            Ok(expected) => expected.to_rgba(),
+            Err(e) => {
+                failures.push(format!(
+                    "{}: no reference image at {} ({}); run with SHADERTOY_UPDATE_GOLDEN=1 to \
+                     create it",
+                    shader.name,
+                    golden_path.display(),
+                    e
+                ));
+                continue;
+            }
+        };
+        let actual = image::open(&actual_path).unwrap().to_rgba();
+        if expected.dimensions() != actual.dimensions() {
+            failures.push(format!(
+                "{}: size {:?} doesn't match reference {:?}",
+                shader.name,
+                actual.dimensions(),
+                expected.dimensions()
+            ));
+            continue;
+        }
+
+        let (mismatched, diff) = compare(&expected, &actual, tolerance);
+        if mismatched > 0 {
+            diff.save(&diff_path).unwrap();
+            failures.push(format!(
+                "{}: {} pixels differ by more than {} (rendered: {}, diff: {})",
+                shader.name,
+                mismatched,
+                tolerance,
+                actual_path.display(),
+                diff_path.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}